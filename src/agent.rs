@@ -1,237 +1,1751 @@
-use std::{ops::Deref, rc::Rc};
+use std::{
+    cmp::Reverse,
+    mem,
+    ops::Deref,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    board::{Game, Player},
-    tile::{Position, TileIndex},
+    bitboard::Bitboard,
+    board::Game,
+    edge::Edge,
+    endgame::loony_endgame_value,
+    strategy::{Move, Strategy},
+    tile::TileIndex,
+    transposition::{Bound, Entry, TableStats, TranspositionTable},
 };
 
+/// Alpha-beta search of the game on the perspective of the player to play,
+/// who can be either player: one agent can play both sides.
 pub struct Agent {
-    game: Rc<Game>,
-    turn: Player,
+    game: Arc<Game>,
+    /// Only ever shared with the helpers of a parallel search, while they
+    /// search.
+    table: Arc<TranspositionTable>,
+    limits: SearchLimits,
+    budget: Budget,
+    nodes: u64,
+    /// Positions the helpers of the last search visited while the busiest
+    /// of them was still searching.
+    overlapped: u64,
+    root_ply: usize,
+    root_moves: Vec<MoveScore>,
+    threads: usize,
+    /// Agents searching the moves shared out by a parallel search on the
+    /// other threads, each with its own transposition table.
+    helpers: Vec<Agent>,
+    /// The table of the agent that started a parallel search, which its
+    /// helpers consult without writing to it.
+    shared: Option<Arc<TranspositionTable>>,
+    settings: Settings,
+    /// Two moves per ply from the root that recently cut the search off.
+    killers: Vec<[Option<Action>; 2]>,
+    /// How much each line cut the search off so far, by line number.
+    history: Vec<u32>,
 }
 
-const ENABLE_DEBUG: bool = false;
+/// Budget for [`Agent::search_with_limit`]. The search deepens iteratively
+/// until any of the limits is reached and answers with the best move of the
+/// deepest iteration it completed.
+#[derive(Debug, Clone, Default)]
+pub struct SearchLimits {
+    /// Wall-clock time the search may take.
+    pub time: Option<Duration>,
+    /// Number of positions the search may visit.
+    pub nodes: Option<u64>,
+    /// Number of plies the search may look ahead, at least one.
+    pub depth: Option<u32>,
+    /// Flag that aborts the search as soon as it is set, from any thread.
+    pub stop: Option<Arc<AtomicBool>>,
+}
 
-type Action = (TileIndex, Position);
-const NULL_ACTION: Action = ((3, 3), Position::Right);
+impl SearchLimits {
+    fn is_unlimited(&self) -> bool {
+        self.time.is_none() && self.nodes.is_none() && self.depth.is_none() && self.stop.is_none()
+    }
+}
+
+/// Everything a search found out about the position.
+#[derive(Debug, Clone)]
+pub struct SearchResult {
+    /// The move to play, `None` if the game is over.
+    pub best_move: Option<Action>,
+    /// Final box difference expected with best play, on the perspective of
+    /// the player to play.
+    pub value: i32,
+    /// The best move followed by the expected replies, as far as the
+    /// transposition table remembers them.
+    pub principal_variation: Vec<Action>,
+    /// Positions visited.
+    pub nodes: u64,
+    pub elapsed: Duration,
+    /// Plies searched by the deepest completed iteration, 0 if none was
+    /// completed.
+    pub depth: u32,
+    /// Scores of the moves searched at the root, best first.
+    pub root_moves: Vec<MoveScore>,
+    /// What the best move does, in words.
+    pub explanation: String,
+}
+
+/// How the agent searches each iteration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Algorithm {
+    /// Alpha-beta with the window of the caller for every move.
+    #[default]
+    AlphaBeta,
+    /// Principal variation search: the first move gets the full window, the
+    /// others a null window proving them no better, and a second search if
+    /// they turn out better after all.
+    Pvs,
+    /// MTD(f): null-window searches only, closing in on the value from the
+    /// previous iteration's value. Scores are small integers, so it takes
+    /// few of them.
+    Mtdf,
+}
+
+/// Score of a root move. Once a better move is known, alpha-beta only proves
+/// that the others are no better, so their value is an upper bound.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MoveScore {
+    pub edge: Action,
+    pub value: i32,
+    pub bound: Bound,
+}
+
+/// How the agent searches, passed on as is to the helpers of a parallel
+/// search.
+#[derive(Debug, Clone, Copy)]
+struct Settings {
+    algorithm: Algorithm,
+    /// Half-width of the window around the previous iteration's value that
+    /// deeper iterations start with.
+    aspiration: Option<i32>,
+    ordering: bool,
+    macros: bool,
+    endgames: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            algorithm: Algorithm::default(),
+            aspiration: None,
+            ordering: true,
+            macros: true,
+            endgames: true,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+struct Budget {
+    deadline: Option<Instant>,
+    nodes: Option<u64>,
+    stop: Option<Arc<AtomicBool>>,
+    exhausted: bool,
+}
+
+/// How many nodes to visit between two looks at the clock.
+const CLOCK_INTERVAL: u64 = 256;
+
+type Action = Edge;
+const NULL_ACTION: Action = Edge::horizontal(usize::MAX, usize::MAX);
+
+/// Bound of every window, safe to negate.
+const INFINITY: i32 = i32::MAX;
+
+/// Plies left below which a parallel search stays on one thread.
+const SPLIT_DEPTH: u32 = 4;
 
 impl Agent {
-    pub fn new(game: Rc<Game>, turn: Player) -> Agent {
-        Self { game, turn }
+    pub fn new(game: Arc<Game>) -> Agent {
+        Self {
+            game,
+            table: Arc::default(),
+            limits: SearchLimits::default(),
+            budget: Budget::default(),
+            nodes: 0,
+            overlapped: 0,
+            root_ply: 0,
+            root_moves: vec![],
+            threads: 1,
+            helpers: vec![],
+            shared: None,
+            settings: Settings::default(),
+            killers: vec![],
+            history: vec![],
+        }
+    }
+
+    /// Limit the search to `depth` plies, one per line drawn. Limited
+    /// searches deepen iteratively and evaluate positions at the horizon
+    /// with [`Game::evaluate`]. `None` searches to the end of the game.
+    pub fn set_max_depth(&mut self, depth: Option<u32>) {
+        self.limits.depth = depth;
     }
 
-    pub fn ab_search(&mut self) -> (Action, i32) {
-        let alpha = i32::MIN;
-        let beta = i32::MAX;
-        self.max(self.game.deref().clone(), alpha, beta)
+    /// Limits applied by [`Agent::ab_search`], and so whenever the agent
+    /// plays as a [`Strategy`].
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
     }
 
-    fn max(&mut self, mut game: Game, mut alpha: i32, beta: i32) -> (Action, i32) {
-        if self.turn != game.player_to_play() {
-            panic!()
+    /// Replace the transposition table with an empty one of `capacity`
+    /// entries.
+    pub fn set_table_capacity(&mut self, capacity: usize) {
+        self.table = Arc::new(TranspositionTable::new(capacity));
+        self.helpers.clear();
+    }
+
+    /// Whether to search captures first, then safe moves, then sacrifices,
+    /// each group in killer then history order. On by default; the moves
+    /// are otherwise searched in board order.
+    pub fn set_move_ordering(&mut self, ordering: bool) {
+        self.settings.ordering = ordering;
+    }
+
+    /// Search every iteration with `algorithm`.
+    pub fn set_algorithm(&mut self, algorithm: Algorithm) {
+        self.settings.algorithm = algorithm;
+    }
+
+    /// Start every iteration after the first with a window of `width` on
+    /// each side of the value of the one before, searching again with the
+    /// full window if the value falls outside. `None`, the default, always
+    /// uses the full window. MTD(f) has no use for it.
+    pub fn set_aspiration_window(&mut self, width: Option<i32>) {
+        self.settings.aspiration = width.map(|width| width.max(1));
+    }
+
+    /// Whether to search the turns in which boxes can be taken as a few
+    /// macro moves below the root: take every box, or decline the last two
+    /// of a chain or the last four of a loop. On by default; every single
+    /// capture is otherwise searched.
+    pub fn set_capture_macros(&mut self, macros: bool) {
+        self.settings.macros = macros;
+    }
+
+    /// Whether to settle loony endgames of independent chains and loops below
+    /// the root with [`loony_endgame_value`] instead of searching them. Those
+    /// with junctions are searched either way. On by default.
+    pub fn set_loony_endgames(&mut self, endgames: bool) {
+        self.settings.endgames = endgames;
+    }
+
+    /// Split the search between `threads` threads along the principal
+    /// variation: at every position of it with a few plies left, the first
+    /// move is searched on this thread, then the others are shared out
+    /// between the threads, each searching its share with the bound the
+    /// first move set and a transposition table of its own, on top of this
+    /// one. The moves go to the threads in a fixed order, so results only
+    /// depend on the number of threads.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    /// Hit, miss and store counts of the transposition table.
+    pub fn table_stats(&self) -> TableStats {
+        self.table.stats()
+    }
+
+    /// Number of positions visited by the last search.
+    pub fn nodes(&self) -> u64 {
+        self.nodes
+    }
+
+    /// Number of positions the last search visited one after the other:
+    /// all of them on one thread, only those of the busiest helper while
+    /// the helpers search. What the search would take with a core per
+    /// thread.
+    pub fn critical_nodes(&self) -> u64 {
+        self.nodes - self.overlapped
+    }
+
+    pub fn ab_search(&mut self) -> SearchResult {
+        self.search_with_limit(self.limits.clone())
+    }
+
+    /// Search within the given limits and return the best move found. Once
+    /// a limit is reached the search is abandoned and the result of the
+    /// deepest completed iteration is returned instead.
+    pub fn search_with_limit(&mut self, limits: SearchLimits) -> SearchResult {
+        let start = Instant::now();
+        let mut game = self.game.deref().clone();
+
+        let remaining = game.available_moves().len() as u32;
+        // While moves are left there is always one to answer with
+        let limit = limits
+            .depth
+            .map_or(remaining, |depth| depth.max(1).min(remaining));
+
+        // Shallow iterations fill the transposition table with best moves
+        // that order the deeper ones
+        let first = if limits.is_unlimited() {
+            limit
+        } else {
+            limit.min(1)
+        };
+
+        self.nodes = 0;
+        self.overlapped = 0;
+        self.root_ply = game.history().len();
+        self.reset_ordering(&game);
+        if self.threads > 1 {
+            self.prepare_helpers(&game);
         }
+        self.budget = Budget {
+            deadline: limits.time.map(|time| start + time),
+            nodes: limits.nodes,
+            stop: limits.stop,
+            exhausted: false,
+        };
 
-        if game.ended() {
-            if ENABLE_DEBUG {
-                let k = game.utility(self.turn);
-                Self::print_mv(&game, NULL_ACTION, k);
+        let mut result = None;
+        let mut root_moves = vec![];
+        let mut reached = 0;
+        for depth in first..=limit {
+            self.root_moves.clear();
+            let previous = result.map(|(_, value)| value);
+            let (action, value) = self.search_root(&mut game, depth, previous);
+
+            if self.budget.exhausted {
+                // The unfinished iteration still beats having nothing
+                if result.is_none() && action != NULL_ACTION {
+                    result = Some((action, value));
+                    root_moves = mem::take(&mut self.root_moves);
+                }
+                break;
             }
 
-            return (NULL_ACTION, game.utility(self.turn));
+            result = Some((action, value));
+            root_moves = mem::take(&mut self.root_moves);
+            reached = depth;
         }
 
-        let mut action = NULL_ACTION;
+        let (action, value) = result.unwrap_or_else(|| {
+            let action = game.available_moves().first().copied();
+            let turn = game.player_to_play();
+            (action.unwrap_or(NULL_ACTION), game.evaluate(turn))
+        });
+        let best_move = (action != NULL_ACTION).then_some(action);
+
+        root_moves.sort_by_key(|score: &MoveScore| Reverse(score.value));
 
-        let mut v = i32::MIN;
-        for (index, pos) in game.available_moves() {
-            let mut new_state = game.clone();
-            new_state.play(index, pos);
+        SearchResult {
+            best_move,
+            value,
+            principal_variation: best_move.map_or(vec![], |action| {
+                self.principal_variation(&mut game, action, reached)
+            }),
+            nodes: self.nodes,
+            elapsed: start.elapsed(),
+            depth: reached,
+            root_moves,
+            explanation: best_move.map_or(String::new(), |action| explain(&game, action)),
+        }
+    }
+
+    /// Search the root of `game` to `depth` with the chosen algorithm,
+    /// `previous` being the value of the iteration before, if any.
+    fn search_root(&mut self, game: &mut Game, depth: u32, previous: Option<i32>) -> (Action, i32) {
+        if self.settings.algorithm == Algorithm::Mtdf {
+            let guess = previous.unwrap_or_else(|| game.evaluate(game.player_to_play()));
+            return self.mtdf(game, depth, guess);
+        }
+
+        let (Some(width), Some(previous)) = (self.settings.aspiration, previous) else {
+            return self.negamax(game, -INFINITY, INFINITY, depth);
+        };
+
+        let window = (
+            previous.saturating_sub(width).max(-INFINITY),
+            previous.saturating_add(width),
+        );
+        let (action, value) = self.negamax(game, window.0, window.1, depth);
+        if self.budget.exhausted || (window.0 < value && value < window.1) {
+            return (action, value);
+        }
 
-            let f = if game.player_to_play() == new_state.player_to_play() {
-                Self::max
+        // The value is only bounded, look again with nothing ruled out
+        self.root_moves.clear();
+        self.negamax(game, -INFINITY, INFINITY, depth)
+    }
+
+    /// MTD(f) on the root of `game`: null-window searches around `guess`
+    /// narrowing the bounds on the value until they meet. The best move is
+    /// the one proving the last lower bound.
+    fn mtdf(&mut self, game: &mut Game, depth: u32, guess: i32) -> (Action, i32) {
+        let (mut lower, mut upper) = (-INFINITY, INFINITY);
+        let mut value = guess.clamp(-INFINITY + 1, INFINITY);
+        let mut best = (NULL_ACTION, value);
+        let mut root_moves = vec![];
+
+        while lower < upper {
+            let beta = if value == lower { value + 1 } else { value };
+
+            self.root_moves.clear();
+            let (action, bound) = self.negamax(game, beta - 1, beta, depth);
+            if self.budget.exhausted {
+                if best.0 == NULL_ACTION {
+                    best = (action, bound);
+                    root_moves = mem::take(&mut self.root_moves);
+                }
+                break;
+            }
+
+            value = bound;
+            if value < beta {
+                upper = value;
             } else {
-                Self::min
+                lower = value;
+                best = (action, value);
+                root_moves = mem::take(&mut self.root_moves);
+            }
+        }
+
+        self.root_moves = root_moves;
+        (best.0, value)
+    }
+
+    /// Follow the best moves stored in the transposition table from the root,
+    /// starting with `action`, for at most `depth` plies.
+    fn principal_variation(&self, game: &mut Game, action: Action, depth: u32) -> Vec<Action> {
+        let mut line = vec![action];
+        game.play(action);
+
+        while line.len() < depth.max(1) as usize {
+            // Below the root, parallel searches fill the helper tables
+            let key = game.hash_key();
+            let next = self
+                .table
+                .peek(key)
+                .or_else(|| {
+                    self.helpers
+                        .iter()
+                        .find_map(|helper| helper.table.peek(key))
+                })
+                .and_then(|entry| entry.best_move())
+                .filter(|&edge| game.is_legal(edge));
+
+            match next {
+                Some(edge) => {
+                    line.push(edge);
+                    game.play(edge);
+                }
+                None => break,
+            }
+        }
+
+        line.iter().for_each(|_| {
+            game.undo();
+        });
+
+        line
+    }
+
+    /// Get one helper per thread ready to search the moves of `game` the
+    /// way this agent would. Each keeps a table of its own between searches,
+    /// the entries it writes copied to this one after every split.
+    fn prepare_helpers(&mut self, game: &Game) {
+        let count = self
+            .threads
+            .min(game.available_moves().len().saturating_sub(1));
+        while self.helpers.len() < count {
+            let mut helper = Agent::new(self.game.clone());
+            helper.table = Arc::new(TranspositionTable::with_journal(self.table.capacity()));
+            self.helpers.push(helper);
+        }
+
+        for helper in self.helpers[..count].iter_mut() {
+            helper.root_ply = self.root_ply;
+            helper.settings = self.settings;
+            helper.reset_ordering(game);
+        }
+    }
+
+    /// Search `lines` from `game` to `depth` within `window` on the helpers,
+    /// dealt to them in turn, each on its own thread. The values come back
+    /// in the order of `lines`, `None` for the lines a helper did not get to
+    /// after a cutoff or with the budget exhausted.
+    fn split(
+        &mut self,
+        game: &Game,
+        lines: &[Vec<Action>],
+        depth: u32,
+        window: (i32, i32),
+    ) -> Vec<Option<i32>> {
+        let threads = self.threads.min(lines.len());
+
+        // Node limits are shared out between the helpers
+        let mut budget = self.budget.clone();
+        budget.nodes = budget
+            .nodes
+            .map(|nodes| nodes.saturating_sub(self.nodes) / threads as u64);
+
+        // The helpers read what this agent left in the table, which nobody
+        // writes to until they are done
+        let shared = &self.table;
+        let found: Vec<Vec<i32>> = thread::scope(|scope| {
+            let searches: Vec<_> = self.helpers[..threads]
+                .iter_mut()
+                .enumerate()
+                .map(|(i, helper)| {
+                    let share: Vec<&[Action]> = lines
+                        .iter()
+                        .skip(i)
+                        .step_by(threads)
+                        .map(Vec::as_slice)
+                        .collect();
+                    let mut game = game.clone();
+
+                    helper.nodes = 0;
+                    helper.budget = budget.clone();
+                    helper.shared = Some(shared.clone());
+
+                    scope.spawn(move || helper.search_lines(&mut game, &share, depth, window))
+                })
+                .collect();
+
+            searches
+                .into_iter()
+                .map(|search| search.join().unwrap())
+                .collect()
+        });
+
+        for helper in self.helpers[..threads].iter_mut() {
+            helper.shared = None;
+        }
+
+        // What the helpers wrote is there for the rest of the search, in a
+        // fixed order
+        let table = Arc::get_mut(&mut self.table).expect("the helpers are done");
+        let mut busiest = 0;
+        for helper in self.helpers[..threads].iter_mut() {
+            self.nodes += helper.nodes;
+            self.overlapped += helper.nodes;
+            busiest = busiest.max(helper.nodes);
+            self.budget.exhausted |= helper.budget.exhausted;
+            table.merge(helper.table_mut());
+        }
+        self.overlapped -= busiest;
+
+        (0..lines.len())
+            .map(|i| found[i % threads].get(i / threads).copied())
+            .collect()
+    }
+
+    /// Search `lines` from `game` one after the other to `depth` within
+    /// `window`, narrowing it as they go like [`Agent::negamax`] does past
+    /// the first move, and stopping at a line reaching its upper end.
+    fn search_lines(
+        &mut self,
+        game: &mut Game,
+        lines: &[&[Action]],
+        depth: u32,
+        window: (i32, i32),
+    ) -> Vec<i32> {
+        let (mut alpha, beta) = window;
+        let mut values = vec![];
+
+        for line in lines {
+            let scout = self.settings.algorithm == Algorithm::Pvs;
+            let val = self.search_move(game, line, alpha, beta, depth, scout);
+
+            if self.budget.exhausted {
+                break;
+            }
+
+            values.push(val);
+            if val >= beta {
+                break;
+            }
+            alpha = alpha.max(val);
+        }
+
+        values
+    }
+
+    /// Count a visited node and tell whether the budget has run out.
+    fn out_of_budget(&mut self) -> bool {
+        self.nodes += 1;
+
+        let budget = &mut self.budget;
+        if budget.exhausted {
+            return true;
+        }
+
+        if let Some(nodes) = budget.nodes {
+            budget.exhausted |= self.nodes > nodes;
+        }
+
+        if let Some(stop) = &budget.stop {
+            budget.exhausted |= stop.load(Ordering::Relaxed);
+        }
+
+        if let Some(deadline) = budget.deadline {
+            if self.nodes.is_multiple_of(CLOCK_INTERVAL) {
+                budget.exhausted |= Instant::now() >= deadline;
+            }
+        }
+
+        budget.exhausted
+    }
+
+    /// Alpha-beta search of `game` on the perspective of the player to
+    /// play, within the window `(alpha, beta)`, `depth` plies deep.
+    fn negamax(
+        &mut self,
+        game: &mut Game,
+        mut alpha: i32,
+        mut beta: i32,
+        depth: u32,
+    ) -> (Action, i32) {
+        if self.out_of_budget() {
+            return (NULL_ACTION, 0);
+        }
+
+        let turn = game.player_to_play();
+        if game.ended() {
+            return (NULL_ACTION, game.utility(turn));
+        }
+
+        let ply = game.history().len() - self.root_ply;
+        if let Some(value) = self.endgame_value(game, ply) {
+            return (NULL_ACTION, value);
+        }
+
+        if depth == 0 {
+            return (NULL_ACTION, game.evaluate(turn));
+        }
+
+        let key = game.hash_key();
+        // Searching past the end of the game changes nothing
+        let depth = depth.min(lines_left(game));
+
+        let mut moves = self.node_moves(game, ply);
+
+        let root = ply == 0;
+        if root {
+            // Only for move ordering: the root is always searched with its
+            // full window so that every move gets a score
+            let (mut a, mut b) = (alpha, beta);
+            self.probe(key, depth, &mut a, &mut b, &mut moves);
+        } else if let Some(result) = self.probe(key, depth, &mut alpha, &mut beta, &mut moves) {
+            return result;
+        }
+
+        let window = (alpha, beta);
+        let mut action = NULL_ACTION;
+
+        let mut v = -INFINITY;
+        let mut split: Option<Vec<Option<i32>>> = None;
+        for (i, line) in moves.iter().enumerate() {
+            let edge = line[0];
+            let val = match &split {
+                // Only lines past a cutoff are left out
+                Some(values) => values[i - 1].expect("no line left out before a cutoff"),
+                None => {
+                    let scout = self.settings.algorithm == Algorithm::Pvs && i > 0;
+                    self.search_move(game, line, alpha, beta, depth, scout)
+                }
             };
 
-            let (_, val) = f(self, new_state, alpha, beta);
+            if self.budget.exhausted {
+                // Only moves searched to the end count, and nothing is stored
+                return (action, v);
+            }
+
+            if root {
+                let bound = if val <= alpha {
+                    Bound::Upper
+                } else if val >= beta {
+                    Bound::Lower
+                } else {
+                    Bound::Exact
+                };
+
+                self.root_moves.push(MoveScore {
+                    edge,
+                    value: val,
+                    bound,
+                });
+            }
 
             if val > v {
-                action = (index, pos);
+                action = edge;
                 v = val;
             }
 
             if v >= beta {
-                if ENABLE_DEBUG {
-                    Self::print_mv(&game, (index, pos), v);
-                }
-                return ((index, pos), v);
+                self.remember_cutoff(game, edge, ply, depth);
+                break;
             }
 
             if v > alpha {
                 alpha = v;
             }
-        }
 
-        if ENABLE_DEBUG {
-            Self::print_mv(&game, action, v);
+            // The first move bounds the others, which the helpers share out
+            if i == 0 && self.threads > 1 && depth >= SPLIT_DEPTH && moves.len() > 1 {
+                split = Some(self.split(game, &moves[1..], depth, (alpha, beta)));
+                if self.budget.exhausted {
+                    return (action, v);
+                }
+            }
         }
 
+        self.store(key, depth, window, action, v);
         (action, v)
     }
 
-    fn min(&mut self, mut game: Game, alpha: i32, mut beta: i32) -> (Action, i32) {
-        if self.turn == game.player_to_play() {
-            panic!()
+    /// [`Agent::child`], first with a null window proving the move no
+    /// better than `alpha` if `scout`, and with the full window only if it
+    /// is.
+    fn search_move(
+        &mut self,
+        game: &mut Game,
+        line: &[Action],
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        scout: bool,
+    ) -> i32 {
+        if !scout || alpha + 1 >= beta {
+            return self.child(game, line, alpha, beta, depth);
         }
 
-        if game.ended() {
-            if ENABLE_DEBUG {
-                let k = game.utility(self.turn);
-                Self::print_mv(&game, NULL_ACTION, k);
+        let val = self.child(game, line, alpha, alpha + 1, depth);
+        if alpha < val && val < beta && !self.budget.exhausted {
+            self.child(game, line, alpha, beta, depth)
+        } else {
+            val
+        }
+    }
+
+    /// Value of drawing the lines of `line` in `game`, searched within the
+    /// window `(alpha, beta)` to `depth` plies including this one, on the
+    /// perspective of the player drawing them. The window and the value
+    /// change sides only when the turn passes, a box completed giving the
+    /// same player another move.
+    fn child(
+        &mut self,
+        game: &mut Game,
+        line: &[Action],
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+    ) -> i32 {
+        let turn = game.player_to_play();
+        for &edge in line.iter() {
+            game.play(edge);
+        }
+
+        let val = if turn == game.player_to_play() {
+            self.negamax(game, alpha, beta, depth - 1).1
+        } else {
+            -self.negamax(game, -beta, -alpha, depth - 1).1
+        };
+
+        for _ in line.iter() {
+            game.undo();
+        }
+
+        val
+    }
+
+    /// The exact final score of a loony endgame of independent chains and
+    /// loops, `None` at the root or in any other position.
+    fn endgame_value(&self, game: &Game, ply: usize) -> Option<i32> {
+        if !self.settings.endgames || ply == 0 {
+            return None;
+        }
+
+        loony_endgame_value(game)
+    }
+
+    /// The moves to search at `ply` from the root, each a sequence of lines
+    /// drawn by the same player: the capture macros when boxes can be taken
+    /// below the root, single lines in [`Agent::order_moves`] order
+    /// otherwise.
+    fn node_moves(&self, game: &Game, ply: usize) -> Vec<Vec<Action>> {
+        if self.settings.macros && ply > 0 {
+            if let Some(macros) = capture_macros(game) {
+                return macros;
             }
+        }
+
+        let mut moves = game.available_moves();
+        self.order_moves(game, &mut moves, ply);
+        moves.into_iter().map(|edge| vec![edge]).collect()
+    }
 
-            return (NULL_ACTION, game.utility(self.turn));
+    /// Forget the killers and history of earlier searches.
+    fn reset_ordering(&mut self, game: &Game) {
+        self.killers.clear();
+        self.history = vec![0; game.board().bits().line_count()];
+    }
+
+    /// Sort `moves` at `ply` from the root: boxes taken first, then moves
+    /// giving nothing away, then the ones handing boxes over. Within each
+    /// group killers come first, then the moves with the best history.
+    fn order_moves(&self, game: &Game, moves: &mut [Action], ply: usize) {
+        if !self.settings.ordering {
+            return;
         }
 
-        let mut action = NULL_ACTION;
-        let mut v = i32::MAX;
-        for (index, pos) in game.available_moves() {
-            let mut new_state = game.clone();
-            new_state.play(index, pos);
+        let board = game.board();
+        let size = game.size();
+        let killers = self.killers.get(ply).copied().unwrap_or_default();
 
-            let f = if game.player_to_play() == new_state.player_to_play() {
-                Self::min
+        moves.sort_by_cached_key(|&edge| {
+            let group = if board.completes_box(edge) {
+                0
+            } else if board.is_safe(edge) {
+                1
             } else {
-                Self::max
+                2
             };
+            let killer = killers
+                .iter()
+                .position(|&killer| killer == Some(edge))
+                .unwrap_or(killers.len());
 
-            let (_, val) = f(self, new_state, alpha, beta);
+            (group, killer, Reverse(self.history[edge.line(size)]))
+        });
+    }
 
-            if val < v {
-                action = (index, pos);
-                v = val;
-            }
+    /// Credit `edge` for cutting the search off at `ply` with `depth` plies
+    /// left. Captures come first anyway and are not remembered as killers.
+    fn remember_cutoff(&mut self, game: &Game, edge: Action, ply: usize, depth: u32) {
+        if !self.settings.ordering {
+            return;
+        }
 
-            if v <= alpha {
-                if ENABLE_DEBUG {
-                    Self::print_mv(&game, (index, pos), v);
-                }
+        self.history[edge.line(game.size())] += depth * depth;
 
-                return ((index, pos), v);
-            }
+        if game.board().completes_box(edge) {
+            return;
+        }
+
+        if self.killers.len() <= ply {
+            self.killers.resize(ply + 1, [None; 2]);
+        }
+
+        let killers = &mut self.killers[ply];
+        if killers[0] != Some(edge) {
+            killers[1] = killers[0];
+            killers[0] = Some(edge);
+        }
+    }
+
+    /// Consult the transposition table: move the sequence starting with the
+    /// stored best move to the front of `moves`, narrow the window with a
+    /// stored bound and return the stored result if it settles the position.
+    fn probe(
+        &mut self,
+        key: u64,
+        depth: u32,
+        alpha: &mut i32,
+        beta: &mut i32,
+        moves: &mut [Vec<Action>],
+    ) -> Option<(Action, i32)> {
+        // A helper goes by the deeper of its own entry and the shared one
+        let own = self.table_mut().probe(key);
+        let shared = self.shared.as_ref().and_then(|table| table.peek(key));
+        let entry = match (own, shared) {
+            (Some(own), Some(shared)) if shared.depth() > own.depth() => shared,
+            (own, shared) => own.or(shared)?,
+        };
+        let action = entry.best_move().unwrap_or(NULL_ACTION);
+
+        if let Some(i) = moves.iter().position(|line| line[0] == action) {
+            moves[..=i].rotate_right(1);
+        }
+
+        if entry.depth() < depth {
+            return None;
+        }
+
+        let value = entry.value();
+        match entry.bound() {
+            Bound::Exact => return Some((action, value)),
+            Bound::Lower => *alpha = (*alpha).max(value),
+            Bound::Upper => *beta = (*beta).min(value),
+        }
+
+        if *alpha >= *beta {
+            return Some((action, value));
+        }
+
+        None
+    }
+
+    fn store(&mut self, key: u64, depth: u32, window: (i32, i32), action: Action, value: i32) {
+        let bound = if value <= window.0 {
+            Bound::Upper
+        } else if value >= window.1 {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+
+        let best_move = if action == NULL_ACTION {
+            None
+        } else {
+            Some(action)
+        };
+
+        self.table_mut()
+            .store(Entry::new(key, value, bound, depth, best_move));
+    }
+
+    fn table_mut(&mut self) -> &mut TranspositionTable {
+        Arc::get_mut(&mut self.table).expect("the table is shared with the helpers")
+    }
+}
 
-            if v < beta {
-                beta = v;
+impl Strategy for Agent {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        // Stored values are on the perspective of the player to play, which
+        // the keys tell apart, so the table stays valid whoever plays next
+        self.game = Arc::new(game.clone());
+        self.ab_search().best_move.expect("no move left to choose")
+    }
+}
+
+/// Describe what playing `action` in `game` does, from the boxes it takes or
+/// hands over and the chains and loops left on the board.
+fn explain(game: &Game, action: Action) -> String {
+    let mut after = game.clone();
+    let taken = after
+        .try_play(action)
+        .map_or(0, |outcome| outcome.completed().len());
+
+    if taken > 0 {
+        return format!("takes {}", boxes(taken));
+    }
+
+    let given = capture_all(&mut after.clone());
+    if given == 0 {
+        let safe = after.board().safe_moves_count();
+        return format!("plays safe, {} safe move(s) left", safe);
+    }
+
+    if capture_all(&mut game.clone()) > 0 {
+        // Declining boxes that could have been taken only makes sense to
+        // stay in control of what is left once the opponent takes them
+        capture_all(&mut after);
+        let board = after.board();
+
+        let kept = if board.get_chains().iter().any(|chain| chain.is_long()) {
+            " to keep control of the long chain"
+        } else if !board.get_loops().is_empty() {
+            " to keep control of the loop"
+        } else {
+            ""
+        };
+
+        return format!("sacrifices {}{}", boxes(given), kept);
+    }
+
+    let chains = game.board().get_chains();
+    let shortest = chains.iter().map(|chain| chain.len()).min();
+
+    match shortest {
+        Some(len) if given <= len => format!("opens the shortest chain, giving {}", boxes(given)),
+        _ => format!("gives away {}", boxes(given)),
+    }
+}
+
+/// Number of lines not drawn yet.
+fn lines_left(game: &Game) -> u32 {
+    let bits = game.board().bits();
+    bits.line_count() as u32 - bits.lines().count_ones()
+}
+
+/// How taking a box with its last line plays out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Capture {
+    /// Nothing else becomes capturable, or the box across the line is
+    /// taken along: there is no reason not to take it.
+    Free,
+    /// The box across the line becomes capturable in turn, with more to
+    /// take after it.
+    Continue,
+    /// Only two boxes are left in the chain, or four in the loop: drawing
+    /// the line given instead declines them all.
+    Tail(usize),
+}
+
+/// The turns worth searching when boxes can be taken, each a sequence of
+/// lines, `None` when there is nothing to take.
+///
+/// Boxes are taken as long as that gives nothing up. Once only the ends of
+/// chains and loops are left to take, the turn either takes everything or,
+/// for each of those ends, takes the rest and declines its last two boxes
+/// (four in a loop) with the double-dealing line, keeping control.
+fn capture_macros(game: &Game) -> Option<Vec<Vec<Action>>> {
+    let size = game.size();
+    let mut bits = *game.board().bits();
+    let mut taken = vec![];
+
+    loop {
+        let captures = captures(&bits);
+        let step = captures
+            .iter()
+            .find(|capture| capture.2 == Capture::Free)
+            .or_else(|| {
+                captures
+                    .iter()
+                    .find(|capture| capture.2 == Capture::Continue)
+            });
+
+        match step {
+            Some(&(_, line, _)) => {
+                bits.draw(line);
+                taken.push(line);
             }
+            None if captures.is_empty() && taken.is_empty() => return None,
+            None => break,
         }
+    }
+
+    let mut declines: Vec<usize> = captures(&bits)
+        .into_iter()
+        .filter_map(|(_, _, capture)| match capture {
+            Capture::Tail(line) => Some(line),
+            _ => None,
+        })
+        .collect();
+    declines.sort_unstable();
+    declines.dedup();
+
+    let mut macros = vec![];
 
-        if ENABLE_DEBUG {
-            Self::print_mv(&game, action, v);
+    // Taking everything
+    let mut all = taken.clone();
+    let mut after = bits;
+    while let Some(&(_, line, _)) = captures(&after).first() {
+        after.draw(line);
+        all.push(line);
+    }
+    macros.push(all);
+
+    // Declining one end after taking the others
+    for &decline in declines.iter() {
+        let mut line_up = taken.clone();
+        let mut after = bits;
+
+        while let Some(&(_, line, _)) = captures(&after)
+            .iter()
+            .find(|&&(_, line, capture)| capture != Capture::Tail(decline) && line != decline)
+        {
+            after.draw(line);
+            line_up.push(line);
         }
 
-        (action, v)
+        // Taking the others may have joined the end to more boxes
+        if captures(&after)
+            .iter()
+            .any(|&(_, _, capture)| capture == Capture::Tail(decline))
+        {
+            line_up.push(decline);
+            macros.push(line_up);
+        }
+    }
+
+    Some(
+        macros
+            .into_iter()
+            .map(|lines| {
+                lines
+                    .into_iter()
+                    .map(|line| Edge::from_line(line, size))
+                    .collect()
+            })
+            .collect(),
+    )
+}
+
+/// Every box that can be taken, with the line taking it and what taking it
+/// leads to.
+fn captures(bits: &Bitboard) -> Vec<(TileIndex, usize, Capture)> {
+    let open_line = |index: TileIndex, except: Option<usize>| {
+        let except = except.map_or(0, |line| 1 << line);
+        let open = bits.box_lines(index) & !bits.lines() & !except;
+        (open != 0).then(|| open.trailing_zeros() as usize)
+    };
+    let across = |index: TileIndex, line: usize| {
+        bits.line_boxes(line)
+            .into_iter()
+            .flatten()
+            .find(|&other| other != index)
+    };
+
+    let mut captures = vec![];
+    for x in 0..bits.rows() {
+        for y in 0..bits.cols() {
+            let index = (x, y);
+            if bits.marked_count(index) != 3 {
+                continue;
+            }
+
+            let line = open_line(index, None).unwrap();
+            let capture = match across(index, line) {
+                Some(next) if bits.marked_count(next) == 2 => {
+                    // The next box is left with one line, leading on
+                    let far = open_line(next, Some(line)).unwrap();
+                    match across(next, far) {
+                        Some(beyond) if bits.marked_count(beyond) == 3 => Capture::Continue,
+                        Some(beyond) if bits.marked_count(beyond) == 2 => {
+                            let last = open_line(beyond, Some(far)).unwrap();
+                            match across(beyond, last) {
+                                Some(end) if end != index && bits.marked_count(end) == 3 => {
+                                    Capture::Tail(far)
+                                }
+                                _ => Capture::Continue,
+                            }
+                        }
+                        _ => Capture::Tail(far),
+                    }
+                }
+                _ => Capture::Free,
+            };
+
+            captures.push((index, line, capture));
+        }
     }
 
-    fn print_mv(game: &Game, mv: Action, value: i32) {
-        game.print_board_without_pad();
-        println!("Move: {:?}", mv);
-        println!("Value: {}", value);
-        println!("Turn: {:?}", game.player_to_play());
-        println!();
+    captures
+}
+
+/// Let the player to play take every box they can in a row and return how
+/// many they took.
+fn capture_all(game: &mut Game) -> usize {
+    let mut taken = 0;
+
+    while let Some(edge) = game
+        .available_moves()
+        .into_iter()
+        .find(|&edge| game.board().completes_box(edge))
+    {
+        taken += game
+            .try_play(edge)
+            .map_or(0, |outcome| outcome.completed().len());
+    }
+
+    taken
+}
+
+fn boxes(count: usize) -> String {
+    if count == 1 {
+        "1 box".to_string()
+    } else {
+        format!("{} boxes", count)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::rc::Rc;
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
 
     use crate::{
         board::{Game, Player},
+        edge::Edge,
+        rng::Rng,
+        strategy::Strategy,
         tile::{
             Position, BOTTOM_CENTER, BOTTOM_LEFT, BOTTOM_RIGHT, CENTER, MIDDLE_LEFT, MIDDLE_RIGHT,
             TOP_CENTER, TOP_LEFT, TOP_RIGHT,
         },
+        transposition::Bound,
     };
 
-    use super::Agent;
+    use super::{explain, Agent, Algorithm, SearchLimits};
+
+    /// Position of test `a`, Even to play.
+    fn position_a() -> Game {
+        let mut game = Game::new();
+        game.play((TOP_LEFT, Position::Top));
+        game.play((TOP_LEFT, Position::Bottom));
+        game.play((TOP_CENTER, Position::Bottom));
+        game.play((TOP_CENTER, Position::Right));
+        game.play((TOP_RIGHT, Position::Right));
+        game.play((MIDDLE_LEFT, Position::Left));
+        game.play((CENTER, Position::Bottom));
+        game.play((MIDDLE_RIGHT, Position::Bottom));
+        game.play((MIDDLE_RIGHT, Position::Right));
+        game.play((BOTTOM_LEFT, Position::Right));
+        game.play((BOTTOM_LEFT, Position::Left));
+        game
+    }
 
     #[test]
     fn a() {
-        let mut game = Game::new();
-        game.play(TOP_LEFT, Position::Top);
-        game.play(TOP_LEFT, Position::Bottom);
-        game.play(TOP_CENTER, Position::Bottom);
-        game.play(TOP_CENTER, Position::Right);
-        game.play(TOP_RIGHT, Position::Right);
-        game.play(MIDDLE_LEFT, Position::Left);
-        game.play(CENTER, Position::Bottom);
-        game.play(MIDDLE_RIGHT, Position::Bottom);
-        game.play(MIDDLE_RIGHT, Position::Right);
-        game.play(BOTTOM_LEFT, Position::Right);
-        game.play(BOTTOM_LEFT, Position::Left);
+        let game = position_a();
 
         // Agent
-        // game.play(TOP_LEFT, Position::Right);
-        // game.play(TOP_LEFT, Position::Left);
-        // game.play(TOP_CENTER, Position::Top);
-        // game.play(BOTTOM_RIGHT, Position::Right);
-        // game.play(TOP_RIGHT, Position::Top);
-        // game.play(MIDDLE_RIGHT, Position::Top);
-        // game.play(MIDDLE_RIGHT, Position::Left);
-        // game.play(MIDDLE_LEFT, Position::Right);
-        // game.play(MIDDLE_LEFT, Position::Bottom);
-        // game.play(BOTTOM_LEFT, Position::Bottom);
-
-        // game.play(BOTTOM_CENTER, Position::Bottom);
-        // game.play(BOTTOM_RIGHT, Position::Bottom);
+        // game.play((TOP_LEFT, Position::Right));
+        // game.play((TOP_LEFT, Position::Left));
+        // game.play((TOP_CENTER, Position::Top));
+        // game.play((BOTTOM_RIGHT, Position::Right));
+        // game.play((TOP_RIGHT, Position::Top));
+        // game.play((MIDDLE_RIGHT, Position::Top));
+        // game.play((MIDDLE_RIGHT, Position::Left));
+        // game.play((MIDDLE_LEFT, Position::Right));
+        // game.play((MIDDLE_LEFT, Position::Bottom));
+        // game.play((BOTTOM_LEFT, Position::Bottom));
+
+        // game.play((BOTTOM_CENTER, Position::Bottom));
+        // game.play((BOTTOM_RIGHT, Position::Bottom));
 
         game.print_board();
 
-        let mut agent = Agent::new(Rc::new(game), Player::Even);
+        let mut agent = Agent::new(Arc::new(game));
         println!("{:?}", agent.ab_search())
     }
 
-    #[test]
-    fn b() {
+    /// Position of test `b`, Even to play.
+    fn position_b() -> Game {
         let mut game = Game::new();
-        game.play(TOP_LEFT, Position::Top);
-        game.play(TOP_CENTER, Position::Top);
-        game.play(TOP_RIGHT, Position::Top);
+        game.play((TOP_LEFT, Position::Top));
+        game.play((TOP_CENTER, Position::Top));
+        game.play((TOP_RIGHT, Position::Top));
 
-        game.play(TOP_LEFT, Position::Bottom);
-        game.play(TOP_CENTER, Position::Bottom);
-        game.play(TOP_RIGHT, Position::Bottom);
+        game.play((TOP_LEFT, Position::Bottom));
+        game.play((TOP_CENTER, Position::Bottom));
+        game.play((TOP_RIGHT, Position::Bottom));
 
-        game.play(BOTTOM_LEFT, Position::Top);
-        game.play(BOTTOM_CENTER, Position::Top);
-        game.play(BOTTOM_RIGHT, Position::Top);
+        game.play((BOTTOM_LEFT, Position::Top));
+        game.play((BOTTOM_CENTER, Position::Top));
+        game.play((BOTTOM_RIGHT, Position::Top));
 
-        game.play(TOP_LEFT, Position::Right);
-        game.play(MIDDLE_LEFT, Position::Right);
-        game.play(BOTTOM_LEFT, Position::Right);
+        game.play((TOP_LEFT, Position::Right));
+        game.play((MIDDLE_LEFT, Position::Right));
+        game.play((BOTTOM_LEFT, Position::Right));
 
-        game.play(BOTTOM_LEFT, Position::Bottom);
-        game.play(BOTTOM_RIGHT, Position::Right);
+        game.play((BOTTOM_LEFT, Position::Bottom));
+        game.play((BOTTOM_RIGHT, Position::Right));
 
-        game.play(TOP_LEFT, Position::Left);
-        game.play(MIDDLE_LEFT, Position::Left);
-        game.play(BOTTOM_LEFT, Position::Left);
+        game.play((TOP_LEFT, Position::Left));
+        game.play((MIDDLE_LEFT, Position::Left));
+        game.play((BOTTOM_LEFT, Position::Left));
 
-        game.play(TOP_RIGHT, Position::Right);
-        game.play(TOP_RIGHT, Position::Left);
+        game.play((TOP_RIGHT, Position::Right));
+        game.play((TOP_RIGHT, Position::Left));
+        game
+    }
+
+    #[test]
+    fn b() {
+        let game = position_b();
 
         // Agent
-        // game.play(MIDDLE_RIGHT, Position::Right);
-        // game.play(MIDDLE_RIGHT, Position::Left);
-        // game.play(BOTTOM_CENTER, Position::Bottom);
+        // game.play((MIDDLE_RIGHT, Position::Right));
+        // game.play((MIDDLE_RIGHT, Position::Left));
+        // game.play((BOTTOM_CENTER, Position::Bottom));
 
-        // game.play(TOP_RIGHT, Position::Left);
-        // game.play(MIDDLE_RIGHT, Position::Left);
-        // game.play(MIDDLE_RIGHT, Position::Right);
+        // game.play((TOP_RIGHT, Position::Left));
+        // game.play((MIDDLE_RIGHT, Position::Left));
+        // game.play((MIDDLE_RIGHT, Position::Right));
 
         game.print_board();
         println!("Turn : {:?}", game.player_to_play());
-        let mut agent = Agent::new(Rc::new(game), Player::Even);
+        let mut agent = Agent::new(Arc::new(game));
         println!("{:?}", agent.ab_search())
     }
+
+    #[test]
+    fn move_ordering() {
+        for game in [position_a(), position_b()] {
+            let game = Arc::new(game);
+
+            let mut plain = Agent::new(game.clone());
+            plain.set_move_ordering(false);
+            let unordered = plain.ab_search();
+
+            let mut agent = Agent::new(game);
+            let ordered = agent.ab_search();
+
+            assert_eq!(ordered.value, unordered.value);
+            assert!(
+                ordered.nodes < unordered.nodes,
+                "{} nodes with ordering, {} without",
+                ordered.nodes,
+                unordered.nodes
+            );
+        }
+    }
+
+    #[test]
+    fn capture_macros() {
+        // A three-box chain opened at its left end on a 1×3 strip
+        let mut game = Game::with_size(1, 3);
+        assert_eq!(super::capture_macros(&game), None);
+        for edge in [
+            "h 0 0", "h 0 1", "h 0 2", "h 1 0", "h 1 1", "h 1 2", "v 0 0",
+        ] {
+            game.play(edge.parse::<Edge>().unwrap());
+        }
+
+        // Take all three, or take one and hand the last two back
+        assert_eq!(
+            super::capture_macros(&game),
+            Some(vec![
+                vec![
+                    Edge::vertical(0, 1),
+                    Edge::vertical(0, 2),
+                    Edge::vertical(0, 3)
+                ],
+                vec![Edge::vertical(0, 1), Edge::vertical(0, 3)],
+            ])
+        );
+    }
+
+    #[test]
+    fn capture_macros_match_full_search() {
+        let mut rng = Rng::new(22);
+        let (mut full_nodes, mut macro_nodes) = (0, 0);
+
+        for (rows, cols, drawn) in [(2, 2, 4), (2, 2, 6), (2, 3, 8), (2, 3, 10), (3, 3, 14)] {
+            for _ in 0..6 {
+                let mut game = Game::with_size(rows, cols);
+                for _ in 0..drawn {
+                    let edge = *rng.choose(&game.available_moves()).unwrap();
+                    game.play(edge);
+                }
+                if game.ended() {
+                    continue;
+                }
+
+                let game = Arc::new(game);
+
+                let mut full = Agent::new(game.clone());
+                full.set_capture_macros(false);
+                let full = full.ab_search();
+
+                let mut agent = Agent::new(game.clone());
+                let result = agent.ab_search();
+
+                assert_eq!(result.value, full.value, "{:?}", game);
+                full_nodes += full.nodes;
+                macro_nodes += result.nodes;
+            }
+        }
+
+        assert!(
+            macro_nodes < full_nodes,
+            "{} nodes with macros, {} without",
+            macro_nodes,
+            full_nodes
+        );
+    }
+
+    #[test]
+    fn loony_endgames() {
+        for game in [position_a(), position_b()] {
+            let game = Arc::new(game);
+
+            let mut plain = Agent::new(game.clone());
+            plain.set_loony_endgames(false);
+            let searched = plain.ab_search();
+
+            let mut agent = Agent::new(game);
+            let settled = agent.ab_search();
+
+            assert_eq!(settled.value, searched.value);
+            assert!(
+                settled.nodes < searched.nodes,
+                "{} nodes with loony endgames, {} without",
+                settled.nodes,
+                searched.nodes
+            );
+        }
+    }
+
+    #[test]
+    fn algorithms() {
+        let mut game = Game::with_size(3, 3);
+        let mut rng = Rng::new(25);
+        for _ in 0..8 {
+            let edge = *rng.choose(&game.available_moves()).unwrap();
+            game.play(edge);
+        }
+
+        for (game, depth) in [(position_a(), None), (position_b(), None), (game, Some(6))] {
+            let game = Arc::new(game);
+            let search = |algorithm, aspiration| {
+                let mut agent = Agent::new(game.clone());
+                agent.set_max_depth(depth);
+                agent.set_algorithm(algorithm);
+                agent.set_aspiration_window(aspiration);
+                agent.ab_search()
+            };
+
+            let plain = search(Algorithm::AlphaBeta, None);
+            for (algorithm, aspiration) in [
+                (Algorithm::Pvs, None),
+                (Algorithm::Mtdf, None),
+                (Algorithm::AlphaBeta, Some(1)),
+                (Algorithm::Pvs, Some(2)),
+            ] {
+                let result = search(algorithm, aspiration);
+                let best = result.best_move.unwrap();
+                assert_eq!(
+                    result.value, plain.value,
+                    "{:?} {:?}",
+                    algorithm, aspiration
+                );
+                assert!(game.is_legal(best));
+
+                if depth.is_none() {
+                    // The best move is worth the value found
+                    let mut after = (*game).clone();
+                    after.play(best);
+                    let value = Agent::new(Arc::new(after.clone())).ab_search().value;
+                    let value = if after.player_to_play() == game.player_to_play() {
+                        value
+                    } else {
+                        -value
+                    };
+                    assert_eq!(value, result.value, "{:?} {:?}", algorithm, aspiration);
+                } else {
+                    // Narrow windows cut more off the deeper iterations
+                    assert!(
+                        result.nodes < plain.nodes,
+                        "{:?} {:?}: {} nodes, {} with alpha-beta",
+                        algorithm,
+                        aspiration,
+                        result.nodes,
+                        plain.nodes
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn single_box() {
+        // Whoever draws the third line hands the box over
+        let mut agent = Agent::new(Arc::new(Game::with_size(1, 1)));
+        assert_eq!(agent.ab_search().value, -1);
+    }
+
+    #[test]
+    fn strip() {
+        let mut game = Game::with_size(1, 4);
+        game.play(((0, 0), Position::Top));
+        game.play(((0, 1), Position::Top));
+        game.play(((0, 2), Position::Top));
+        game.play(((0, 3), Position::Top));
+        game.play(((0, 0), Position::Left));
+        game.play(((0, 3), Position::Right));
+        game.print_board();
+
+        // Four-box chain with its bottom side and inner walls still open
+        let mut agent = Agent::new(Arc::new(game));
+        assert_eq!(agent.ab_search().value, 0);
+    }
+
+    #[test]
+    fn either_side() {
+        let mut rng = Rng::new(24);
+
+        for _ in 0..10 {
+            let mut game = Game::with_size(2, 2);
+            for _ in 0..rng.below(8) {
+                let edge = *rng.choose(&game.available_moves()).unwrap();
+                game.play(edge);
+            }
+            if game.ended() {
+                continue;
+            }
+
+            // The best move leads to a position worth the same to whoever
+            // made it, whether or not they play again
+            let result = Agent::new(Arc::new(game.clone())).ab_search();
+            let turn = game.player_to_play();
+            game.play(result.best_move.unwrap());
+
+            let after = if game.ended() {
+                game.utility(game.player_to_play())
+            } else {
+                Agent::new(Arc::new(game.clone())).ab_search().value
+            };
+            let after = if game.player_to_play() == turn {
+                after
+            } else {
+                -after
+            };
+            assert_eq!(after, result.value);
+        }
+    }
+
+    #[test]
+    fn self_play() {
+        let mut game = Game::with_size(2, 2);
+        let mut agent = Agent::new(Arc::new(game.clone()));
+        let value = agent.ab_search().value;
+
+        // One agent on both sides, keeping its table between moves
+        while !game.ended() {
+            let edge = agent.choose_move(&game);
+            game.play(edge);
+        }
+
+        assert_eq!(game.utility(Player::Odd), value);
+    }
+
+    #[test]
+    fn transpositions() {
+        let mut game = Game::with_size(2, 2);
+        game.play(Edge::horizontal(0, 0));
+        game.play(Edge::vertical(1, 2));
+        game.play(Edge::horizontal(1, 1));
+        let game = Arc::new(game);
+
+        let mut agent = Agent::new(game.clone());
+        let first = agent.ab_search();
+        let value = first.value;
+        let stats = agent.table_stats();
+        assert!(stats.hits > 0);
+        assert!(stats.stores > 0);
+
+        // A single-entry table can barely remember anything
+        let mut forgetful = Agent::new(game);
+        forgetful.set_table_capacity(1);
+        assert_eq!(forgetful.ab_search().value, value);
+
+        // A second search only has to search the root moves again, the
+        // positions below them are answered from the table
+        let second = agent.ab_search();
+        assert_eq!(second.value, value);
+        assert_eq!(second.nodes, 1 + second.root_moves.len() as u64);
+        assert!(second.nodes < first.nodes);
+    }
+
+    #[test]
+    fn depth_limited() {
+        let mut game = Game::with_size(2, 2);
+        game.play(Edge::horizontal(0, 0));
+        game.play(Edge::vertical(1, 2));
+        let game = Arc::new(game);
+
+        let mut full = Agent::new(game.clone());
+        let value = full.ab_search().value;
+
+        // Deepening past the end of the game is a full search
+        let mut deep = Agent::new(game.clone());
+        deep.set_max_depth(Some(50));
+        assert_eq!(deep.ab_search().value, value);
+
+        let mut shallow = Agent::new(game.clone());
+        shallow.set_max_depth(Some(2));
+        let result = shallow.ab_search();
+        assert!(game.is_legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn tournament_board() {
+        let game = Arc::new(Game::with_size(5, 5));
+        let mut agent = Agent::new(game.clone());
+        agent.set_max_depth(Some(3));
+
+        let result = agent.ab_search();
+        assert!(game.is_legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn zero_depth() {
+        let game = Arc::new(Game::with_size(2, 2));
+        let mut agent = Agent::new(game.clone());
+
+        let result = agent.search_with_limit(SearchLimits {
+            depth: Some(0),
+            ..SearchLimits::default()
+        });
+        assert!(game.is_legal(result.best_move.unwrap()));
+        assert_eq!(result.depth, 1);
+    }
+
+    #[test]
+    fn time_budget() {
+        let game = Arc::new(Game::with_size(5, 5));
+        let mut agent = Agent::new(game.clone());
+
+        let start = Instant::now();
+        let result = agent.search_with_limit(SearchLimits {
+            time: Some(Duration::from_millis(50)),
+            ..SearchLimits::default()
+        });
+        assert!(game.is_legal(result.best_move.unwrap()));
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn node_budget() {
+        let game = Arc::new(Game::with_size(3, 3));
+        let mut agent = Agent::new(game.clone());
+
+        let result = agent.search_with_limit(SearchLimits {
+            nodes: Some(1000),
+            ..SearchLimits::default()
+        });
+        assert!(game.is_legal(result.best_move.unwrap()));
+        assert!(agent.nodes() <= 1001);
+    }
+
+    #[test]
+    fn cancellation() {
+        let game = Arc::new(Game::with_size(4, 4));
+        let mut agent = Agent::new(game.clone());
+        let stop = Arc::new(AtomicBool::new(true));
+
+        let result = agent.search_with_limit(SearchLimits {
+            stop: Some(stop.clone()),
+            ..SearchLimits::default()
+        });
+        assert!(game.is_legal(result.best_move.unwrap()));
+        assert_eq!(agent.nodes(), 1);
+
+        // Cancelled from another thread while searching
+        stop.store(false, Ordering::Relaxed);
+        let canceller = {
+            let stop = stop.clone();
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(20));
+                stop.store(true, Ordering::Relaxed);
+            })
+        };
+        let result = agent.search_with_limit(SearchLimits {
+            stop: Some(stop),
+            ..SearchLimits::default()
+        });
+        canceller.join().unwrap();
+        assert!(game.is_legal(result.best_move.unwrap()));
+    }
+
+    #[test]
+    fn parallel() {
+        let mut game = Game::with_size(3, 3);
+        for edge in [
+            "h 0 0", "v 1 3", "h 2 1", "v 0 1", "h 3 2", "v 2 0", "h 1 2",
+        ] {
+            game.play(edge.parse::<Edge>().unwrap());
+        }
+        let game = Arc::new(game);
+
+        for algorithm in [Algorithm::AlphaBeta, Algorithm::Pvs, Algorithm::Mtdf] {
+            let search = |threads| {
+                let mut agent = Agent::new(game.clone());
+                agent.set_max_depth(Some(5));
+                agent.set_algorithm(algorithm);
+                agent.set_threads(threads);
+                let result = agent.ab_search();
+                (result, agent.critical_nodes())
+            };
+            let (expected, _) = search(1);
+
+            for threads in [2, 3] {
+                // Same value as a single thread, and the very same search
+                // each time with as many threads
+                let (first, critical) = search(threads);
+                let (second, _) = search(threads);
+                assert_eq!(first.value, expected.value, "{:?}", algorithm);
+                assert_eq!(first.best_move, second.best_move);
+                assert_eq!(first.nodes, second.nodes);
+                assert_eq!(first.root_moves, second.root_moves);
+                assert_eq!(first.principal_variation, second.principal_variation);
+
+                // The helpers start from the bound and the table the first
+                // move left, so splitting the moves adds little work, and
+                // each thread gets less of it than one on its own
+                assert!(
+                    first.nodes < expected.nodes * 2,
+                    "{:?}: {} nodes on {} threads, {} on one",
+                    algorithm,
+                    first.nodes,
+                    threads,
+                    expected.nodes
+                );
+                assert!(critical < expected.nodes, "{:?}", algorithm);
+            }
+        }
+    }
+
+    #[test]
+    fn thread_safety() {
+        fn send_sync<T: Send + Sync>() {}
+
+        send_sync::<Game>();
+        send_sync::<Agent>();
+        send_sync::<crate::mcts::MctsAgent>();
+        send_sync::<crate::tablebase::Tablebase>();
+    }
+
+    #[test]
+    fn search_result() {
+        let mut game = Game::with_size(2, 2);
+        game.play(Edge::horizontal(0, 0));
+        game.play(Edge::vertical(1, 2));
+        game.play(Edge::horizontal(1, 1));
+        let remaining = game.available_moves().len();
+
+        let mut agent = Agent::new(Arc::new(game.clone()));
+        let result = agent.ab_search();
+        let best = result.best_move.unwrap();
+
+        assert_eq!(result.depth, remaining as u32);
+        assert_eq!(result.nodes, agent.nodes());
+        assert!(!result.explanation.is_empty());
+
+        // Every root move is scored and none beats the best one
+        assert_eq!(result.root_moves.len(), remaining);
+        assert_eq!(result.root_moves[0].edge, best);
+        assert_eq!(result.root_moves[0].value, result.value);
+        assert_eq!(result.root_moves[0].bound, Bound::Exact);
+        assert!(result
+            .root_moves
+            .iter()
+            .all(|score| score.value <= result.value));
+
+        // The principal variation is a legal line of play from the root
+        assert_eq!(result.principal_variation[0], best);
+        for &edge in result.principal_variation.iter() {
+            game.play(edge);
+        }
+    }
+
+    #[test]
+    fn explanations() {
+        // Three boxes in a row, closed on the left and open on the right
+        let mut game = Game::with_size(1, 3);
+        for col in 0..3 {
+            game.play(Edge::horizontal(0, col));
+            game.play(Edge::horizontal(1, col));
+        }
+        game.play(Edge::vertical(0, 0));
+
+        assert_eq!(explain(&game, Edge::vertical(0, 1)), "takes 1 box");
+        game.play(Edge::vertical(0, 1));
+
+        // Leaving the last two boxes to the opponent
+        assert!(explain(&game, Edge::vertical(0, 3)).starts_with("sacrifices 2 boxes"));
+
+        let game = Game::with_size(2, 2);
+        assert!(explain(&game, Edge::horizontal(0, 0)).starts_with("plays safe"));
+    }
 }
@@ -0,0 +1,265 @@
+use crate::{
+    board::Player,
+    edge::Edge,
+    tile::{BoardSize, Position, TileIndex},
+};
+
+/// Maximum number of lines a [`Bitboard`] can hold, one bit each.
+pub const MAX_LINES: usize = u128::BITS as usize;
+
+/// Maximum number of boxes a [`Bitboard`] can hold, one bit each.
+pub const MAX_BOXES: usize = u64::BITS as usize;
+
+/// Compact game state: every line is a bit in `lines` and every box is a bit
+/// in the owner mask of the player who acquired it.
+///
+/// Horizontal lines come first, numbered row by row (`(rows + 1) * cols` of
+/// them), followed by the vertical lines (`rows * (cols + 1)` of them). Boxes
+/// are numbered row by row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Bitboard {
+    rows: u8,
+    cols: u8,
+    lines: u128,
+    owners: [u64; 2],
+}
+
+impl Bitboard {
+    pub fn new(rows: usize, cols: usize) -> Self {
+        if rows == 0 || cols == 0 {
+            panic!("board must have at least one box")
+        }
+
+        if rows * cols > MAX_BOXES || line_count(rows, cols) > MAX_LINES {
+            panic!(
+                "a {}x{} board does not fit in a bitboard ({} lines, {} boxes at most)",
+                rows, cols, MAX_LINES, MAX_BOXES
+            )
+        }
+
+        Self {
+            rows: rows as u8,
+            cols: cols as u8,
+            lines: 0,
+            owners: [0, 0],
+        }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows as usize
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols as usize
+    }
+
+    pub fn size(&self) -> BoardSize {
+        (self.rows(), self.cols())
+    }
+
+    pub fn line_count(&self) -> usize {
+        line_count(self.rows(), self.cols())
+    }
+
+    pub fn box_count(&self) -> usize {
+        self.rows() * self.cols()
+    }
+
+    pub fn lines(&self) -> u128 {
+        self.lines
+    }
+
+    pub fn contains(&self, index: TileIndex) -> bool {
+        index.0 < self.rows() && index.1 < self.cols()
+    }
+
+    /// Line number of the `pos` side of the box at `index`.
+    pub fn line(&self, index: TileIndex, pos: Position) -> usize {
+        Edge::from_tile(index, pos).line(self.size())
+    }
+
+    /// Mask of the four lines around the box at `index`.
+    pub fn box_lines(&self, index: TileIndex) -> u128 {
+        (1 << self.line(index, Position::Top))
+            | (1 << self.line(index, Position::Bottom))
+            | (1 << self.line(index, Position::Left))
+            | (1 << self.line(index, Position::Right))
+    }
+
+    /// The boxes on either side of a line, at most two.
+    pub fn line_boxes(&self, line: usize) -> [Option<TileIndex>; 2] {
+        Edge::from_line(line, self.size())
+            .tiles(self.size())
+            .map(|tile| tile.map(|(index, _)| index))
+    }
+
+    pub fn is_drawn(&self, line: usize) -> bool {
+        self.lines & (1 << line) != 0
+    }
+
+    pub fn is_marked(&self, index: TileIndex, pos: Position) -> bool {
+        self.is_drawn(self.line(index, pos))
+    }
+
+    pub fn marked_count(&self, index: TileIndex) -> u32 {
+        (self.lines & self.box_lines(index)).count_ones()
+    }
+
+    pub fn is_complete(&self, index: TileIndex) -> bool {
+        self.marked_count(index) == 4
+    }
+
+    /// Draw a line and return how many boxes it completed. Drawing a line
+    /// twice completes nothing.
+    pub fn draw(&mut self, line: usize) -> usize {
+        if self.is_drawn(line) {
+            return 0;
+        }
+
+        self.lines |= 1 << line;
+
+        self.line_boxes(line)
+            .iter()
+            .flatten()
+            .filter(|&&index| self.is_complete(index))
+            .count()
+    }
+
+    /// Draw a line and give the boxes it completed to `player`.
+    pub fn draw_for(&mut self, line: usize, player: Player) -> usize {
+        let completed = self.draw(line);
+
+        if completed > 0 {
+            for index in self.line_boxes(line).into_iter().flatten() {
+                if self.is_complete(index) {
+                    self.owners[player_slot(player)] |= self.box_bit(index);
+                }
+            }
+        }
+
+        completed
+    }
+
+    /// Erase a line. The boxes on either side of it are no longer complete,
+    /// so they lose their owner.
+    pub fn erase(&mut self, line: usize) {
+        self.lines &= !(1 << line);
+
+        for index in self.line_boxes(line).into_iter().flatten() {
+            let bit = self.box_bit(index);
+            self.owners[0] &= !bit;
+            self.owners[1] &= !bit;
+        }
+    }
+
+    pub fn owner(&self, index: TileIndex) -> Option<Player> {
+        let bit = self.box_bit(index);
+
+        if self.owners[player_slot(Player::Odd)] & bit != 0 {
+            Some(Player::Odd)
+        } else if self.owners[player_slot(Player::Even)] & bit != 0 {
+            Some(Player::Even)
+        } else {
+            None
+        }
+    }
+
+    pub fn owned_count(&self, player: Player) -> u32 {
+        self.owners[player_slot(player)].count_ones()
+    }
+
+    fn box_bit(&self, index: TileIndex) -> u64 {
+        1 << (index.0 * self.cols() + index.1)
+    }
+}
+
+pub fn line_count(rows: usize, cols: usize) -> usize {
+    (rows + 1) * cols + rows * (cols + 1)
+}
+
+fn player_slot(player: Player) -> usize {
+    match player {
+        Player::Odd => 0,
+        Player::Even => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{board::Player, tile::Position};
+
+    use super::Bitboard;
+
+    #[test]
+    fn shared_lines() {
+        let bits = Bitboard::new(3, 4);
+
+        assert_eq!(
+            bits.line((0, 0), Position::Bottom),
+            bits.line((1, 0), Position::Top)
+        );
+        assert_eq!(
+            bits.line((2, 1), Position::Right),
+            bits.line((2, 2), Position::Left)
+        );
+        assert_eq!(bits.line_count(), 31);
+
+        let mut lines = 0u128;
+        for x in 0..3 {
+            for y in 0..4 {
+                lines |= bits.box_lines((x, y));
+            }
+        }
+        assert_eq!(lines.count_ones(), 31);
+    }
+
+    #[test]
+    fn line_boxes() {
+        let bits = Bitboard::new(2, 2);
+
+        for line in 0..bits.line_count() {
+            for index in bits.line_boxes(line).into_iter().flatten() {
+                assert_ne!(bits.box_lines(index) & (1 << line), 0);
+            }
+        }
+
+        let middle = bits.line((0, 0), Position::Right);
+        assert_eq!(bits.line_boxes(middle), [Some((0, 0)), Some((0, 1))]);
+    }
+
+    #[test]
+    fn draw() {
+        let mut bits = Bitboard::new(1, 2);
+
+        for &(index, pos) in [
+            ((0, 0), Position::Top),
+            ((0, 0), Position::Left),
+            ((0, 0), Position::Bottom),
+            ((0, 1), Position::Top),
+            ((0, 1), Position::Right),
+            ((0, 1), Position::Bottom),
+        ]
+        .iter()
+        {
+            assert_eq!(bits.draw_for(bits.line(index, pos), Player::Odd), 0);
+        }
+
+        let copy = bits;
+        let middle = bits.line((0, 0), Position::Right);
+        assert_eq!(bits.draw_for(middle, Player::Even), 2);
+        assert_eq!(bits.draw_for(middle, Player::Odd), 0);
+        assert_eq!(bits.owner((0, 0)), Some(Player::Even));
+        assert_eq!(bits.owned_count(Player::Even), 2);
+        assert_eq!(bits.owned_count(Player::Odd), 0);
+        assert_eq!(copy.owner((0, 1)), None);
+
+        bits.erase(middle);
+        assert_eq!(bits, copy);
+    }
+
+    #[test]
+    #[should_panic]
+    fn too_large() {
+        Bitboard::new(8, 8);
+    }
+}
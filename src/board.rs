@@ -1,176 +1,168 @@
-use std::{cell::RefCell, fmt::Display, rc::Rc};
-
-use crate::tile::{Chain, ChainBuilder, Loop, LoopBuilder, Position, Tile, TileIndex, POSITIONS};
+use std::{
+    error::Error,
+    fmt::Display,
+    hash::{Hash, Hasher},
+};
+
+use crate::{
+    bitboard::Bitboard,
+    edge::Edge,
+    strings_and_coins::{Node, StringsAndCoins},
+    tile::{BoardSize, Chain, ChainEnd, Loop, Position, Tile, TileIndex},
+    zobrist,
+};
 
 type Matrix<T> = Vec<Vec<T>>;
 
+/// A dots and boxes board. Tiles are views over the underlying [`Bitboard`],
+/// so copying a board is a plain memcpy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Board {
-    tiles: Matrix<Rc<RefCell<Tile>>>,
+    bits: Bitboard,
 }
 
 impl Board {
     pub fn new() -> Self {
-        let mut tiles = Vec::new();
-        for x in 0..3 {
-            let mut row = Vec::new();
-            for y in 0..3 {
-                row.push(Rc::new(RefCell::new(Tile::new((x, y)))));
-            }
-            tiles.push(row);
-        }
-
-        Self { tiles }
+        Self::with_size(3, 3)
     }
 
-    pub fn mark(&mut self, index: TileIndex, pos: Position) {
-        let tile = self.get_tile(index);
-
-        tile.borrow_mut().mark(pos);
-
-        if tile.borrow_mut().has_neighbor(pos) {
-            let neighbor_index = tile.borrow_mut().at_unchecked(pos);
-            let neighbor = self.get_tile(neighbor_index);
-            neighbor.borrow_mut().mark(pos.invert());
+    /// Create an empty board of `rows` × `cols` boxes.
+    pub fn with_size(rows: usize, cols: usize) -> Self {
+        Self {
+            bits: Bitboard::new(rows, cols),
         }
     }
 
-    pub fn acquisitions(&self) -> Matrix<bool> {
-        self.tiles
-            .iter()
-            .map(|row| row.iter().map(|tile| tile.borrow().all_marked()).collect())
-            .collect()
+    pub fn rows(&self) -> usize {
+        self.bits.rows()
     }
 
-    fn get_tile(&mut self, index: TileIndex) -> Rc<RefCell<Tile>> {
-        self.tiles
-            .get_mut(index.0)
-            .unwrap()
-            .get_mut(index.1)
-            .unwrap()
-            .clone()
+    pub fn cols(&self) -> usize {
+        self.bits.cols()
     }
 
-    pub fn get_chains(&mut self) -> Vec<Chain> {
-        let mut has_evaluated = vec![vec![false; 3]; 3];
-        let mut chains = vec![];
-
-        for x in 0..3 {
-            for y in 0..3 {
-                if !has_evaluated[x][y] {
-                    has_evaluated[x][y] = true;
-
-                    let tile = self.get_tile((x, y));
-                    let tile_ref = tile.borrow();
-
-                    if tile_ref.is_end() || tile_ref.is_edge_path_chain_end() {
-                        // Guaranteed to have length 1
-                        let mut builder = ChainBuilder::new(tile.clone());
-
-                        let mut next_chain_tile = self.next_chain_tile(&tile, None);
+    pub fn size(&self) -> BoardSize {
+        self.bits.size()
+    }
 
-                        let mut chaining = false;
-                        while let Some((tile, pos)) = next_chain_tile {
-                            chaining = true;
-                            let index = tile.borrow().index();
+    pub fn bits(&self) -> &Bitboard {
+        &self.bits
+    }
 
-                            if has_evaluated[index.0][index.1] {
-                                // Chain is a loop
-                                break;
-                            }
+    pub fn mark(&mut self, index: TileIndex, pos: Position) {
+        self.bits.draw(self.bits.line(index, pos));
+    }
 
-                            has_evaluated[index.0][index.1] = true;
-                            builder.add(&tile);
-                            next_chain_tile = self.next_chain_tile(&tile, Some(pos));
-                        }
+    /// Mark an edge, give the boxes it completed to `player` and return
+    /// them.
+    pub fn mark_for(&mut self, edge: Edge, player: Player) -> Vec<TileIndex> {
+        let line = edge.line(self.size());
 
-                        if chaining {
-                            chains.push(builder.build());
-                        }
-                    } else {
-                        has_evaluated[x][y] = false;
-                    }
-                }
-            }
+        if self.bits.draw_for(line, player) == 0 {
+            return vec![];
         }
 
-        chains
+        self.bits
+            .line_boxes(line)
+            .into_iter()
+            .flatten()
+            .filter(|&index| self.bits.is_complete(index))
+            .collect()
     }
 
-    pub fn get_loops(&mut self) -> Vec<Loop> {
-        let mut has_evaluated = vec![vec![false; 3]; 3];
-        let mut loops = vec![];
-        let mut indices = vec![];
-
-        for x in 0..3 {
-            for y in 0..3 {
-                if !has_evaluated[x][y] {
-                    has_evaluated[x][y] = true;
-
-                    let mut tile = self.get_tile((x, y));
-                    let tile_ref = tile.borrow();
-
-                    if !tile_ref.is_path() {
-                        continue;
-                    }
-
-                    let mut last_pos = None;
-
-                    drop(tile_ref);
+    /// Erase a marked edge, releasing the boxes it completed.
+    pub fn unmark(&mut self, edge: Edge) {
+        self.bits.erase(edge.line(self.size()));
+    }
 
-                    // let tile_loop = vec![];
-                    let mut builder = LoopBuilder::new(tile.clone());
-                    let mut is_loop = false;
+    pub fn is_drawn(&self, edge: Edge) -> bool {
+        self.bits.is_drawn(edge.line(self.size()))
+    }
 
-                    loop {
-                        let nb = self.get_connected_neighbor(tile.clone(), last_pos);
+    pub fn acquisitions(&self) -> Matrix<bool> {
+        (0..self.rows())
+            .map(|x| {
+                (0..self.cols())
+                    .map(|y| self.bits.is_complete((x, y)))
+                    .collect()
+            })
+            .collect()
+    }
 
-                        if let Some((neighbor, lp)) = nb {
-                            tile = neighbor;
-                            last_pos = Some(lp);
+    pub fn owners(&self) -> Matrix<Option<Player>> {
+        (0..self.rows())
+            .map(|x| (0..self.cols()).map(|y| self.bits.owner((x, y))).collect())
+            .collect()
+    }
 
-                            let tile_index = tile.borrow().index();
-                            has_evaluated[tile_index.0][tile_index.1] = true;
-                            if tile_index == (x, y) {
-                                is_loop = true;
-                                break;
-                            }
+    pub fn get_tile(&self, index: TileIndex) -> Tile {
+        Tile::with_marks(
+            index,
+            self.size(),
+            self.bits.is_marked(index, Position::Top),
+            self.bits.is_marked(index, Position::Bottom),
+            self.bits.is_marked(index, Position::Left),
+            self.bits.is_marked(index, Position::Right),
+        )
+    }
 
-                            indices.push(tile.borrow().index());
-                            if !tile.borrow().is_path() {
-                                break;
-                            }
-                            builder.add(&tile);
-                        } else {
-                            break;
-                        }
-                    }
+    /// Every chain on the board. See [`Board::chains_and_loops`].
+    pub fn get_chains(&self) -> Vec<Chain> {
+        self.chains_and_loops().0
+    }
 
-                    // Reset has eval if loop finding failed
-                    if is_loop {
-                        loops.push(builder.build());
-                    } else {
-                        for &(x, y) in indices.iter() {
-                            has_evaluated[x][y] = false;
-                        }
-                    }
-                }
-            }
-        }
+    /// Every loop on the board. See [`Board::chains_and_loops`].
+    pub fn get_loops(&self) -> Vec<Loop> {
+        self.chains_and_loops().1
+    }
 
-        loops
+    /// Split the boxes with two or three sides drawn into chains and loops.
+    /// Every such box belongs to exactly one of them; the other boxes belong
+    /// to none. Chains and loops come in the row-major order of their
+    /// topmost, then leftmost box, and run from either end.
+    pub fn chains_and_loops(&self) -> (Vec<Chain>, Vec<Loop>) {
+        let cols = self.cols();
+        let index = |coin: usize| (coin / cols, coin % cols);
+        let tiles = |coins: Vec<usize>| -> Vec<Tile> {
+            coins
+                .into_iter()
+                .map(|coin| self.get_tile(index(coin)))
+                .collect()
+        };
+
+        let (coin_chains, coin_loops) = StringsAndCoins::from_board(self).chains_and_loops();
+
+        let chains = coin_chains
+            .into_iter()
+            .map(|chain| {
+                let ends = chain.ends.map(|end| match end {
+                    None => ChainEnd::Closed,
+                    Some(Node::Ground) => ChainEnd::Border,
+                    Some(Node::Coin(coin)) => ChainEnd::Open(index(coin)),
+                });
+
+                Chain::new(tiles(chain.coins), ends)
+            })
+            .collect();
+
+        let loops = coin_loops
+            .into_iter()
+            .map(|coins| Loop::new(tiles(coins)))
+            .collect();
+
+        (chains, loops)
     }
 
-    pub fn free_edge_squares(&mut self) -> i32 {
+    pub fn free_edge_squares(&self) -> i32 {
         let mut sq = 0;
 
-        for x in 0..3 {
-            for y in 0..3 {
+        for x in 0..self.rows() {
+            for y in 0..self.cols() {
                 let tile = self.get_tile((x, y));
-                let tile_ref = tile.borrow();
 
-                if tile_ref.is_end() {
-                    let opening = tile_ref.openings()[0];
-                    if !tile_ref.has_neighbor(opening) {
+                if tile.is_end() {
+                    let opening = tile.openings()[0];
+                    if !tile.has_neighbor(opening) {
                         sq += 1;
                     }
                 }
@@ -180,110 +172,13 @@ impl Board {
         sq
     }
 
-    fn get_connected_neighbor(
-        &mut self,
-        tile: Rc<RefCell<Tile>>,
-        without: Option<Position>,
-    ) -> Option<(Rc<RefCell<Tile>>, Position)> {
-        for &pos in POSITIONS.iter() {
-            if let Some(igored_pos) = without {
-                if pos == igored_pos.invert() {
-                    continue;
-                }
-            }
-
-            let index = tile.borrow().at(pos);
-
-            if let Some(neighbor_index) = index {
-                let neighbor = self.get_tile(neighbor_index);
-                if tile.borrow().connected_to(&neighbor) {
-                    return Some((neighbor, pos));
-                }
-            }
-        }
-
-        None
-    }
-
-    /// last_tile: the last tile in chain
-    /// last_pos: last position to get the last tile in chain
-    /// TODO: refactor
-    fn next_chain_tile(
-        &mut self,
-        last_tile: &Rc<RefCell<Tile>>,
-        last_pos: Option<Position>,
-    ) -> Option<(Rc<RefCell<Tile>>, Position)> {
-        // Assume last_tile has either 1 or 2 openings
-        let last_tile_ref = last_tile.borrow();
-        let mut openings = last_tile_ref.openings();
-
-        if openings.len() == 1 {
-            // Last tile is either start or end of chain
-
-            if let Some(_) = last_pos {
-                // This is not the first tile in the chain
-
-                // End of chain
-                return None;
-            } else {
-                // This wiill be `last_pos` in the next iteration
-                let &next_tile_pos = openings.first().unwrap();
-                let next_tile_index = last_tile_ref.at_unchecked(next_tile_pos);
-
-                let next_tile = self.get_tile(next_tile_index);
-
-                return if next_tile.borrow().can_be_chained() {
-                    Some((next_tile, next_tile_pos))
-                } else {
-                    None
-                };
-            }
-        } else {
-            // Last tile is a path, openings must contain 2 pos
-            // Guaranteed to have last pos because last_tile is path
-            if let Some(pos) = last_pos {
-                if *openings.get(0).unwrap() == pos.invert() {
-                    openings.remove(0);
-                } else {
-                    openings.remove(1);
-                }
-
-                let &next_tile_pos = openings.first().unwrap();
-
-                if let Some(next_tile_index) = last_tile_ref.at(next_tile_pos) {
-                    let next_tile = self.get_tile(next_tile_index);
-
-                    if next_tile.borrow().can_be_chained() {
-                        return Some((next_tile, next_tile_pos));
-                    }
-                }
-
-                return None;
-            } else {
-                // Last tile is path, and it is the first tile in the chain
-                if last_tile_ref.is_edge_path_chain_end() {
-                    let next_tile_pos = last_tile_ref.get_edge_path_chain_pos();
-                    let next_tile_index = last_tile_ref.at_unchecked(next_tile_pos);
-
-                    let next_tile = self.get_tile(next_tile_index);
-
-                    if next_tile.borrow().can_be_chained() {
-                        return Some((next_tile, next_tile_pos));
-                    }
-                }
-                return None;
-            }
-        }
-    }
-
-    pub fn safe_moves_count(&mut self) -> i32 {
+    pub fn safe_moves_count(&self) -> i32 {
         let mut safe_moves = 0;
 
-        for &moves in self.available_moves().iter() {
+        for &edge in self.available_moves().iter() {
             safe_moves += 1;
 
-            let (index, pos) = moves;
-            if self.will_make_end(index, pos) {
+            if !self.is_safe(edge) {
                 safe_moves -= 1;
             }
         }
@@ -291,45 +186,38 @@ impl Board {
         safe_moves
     }
 
-    pub fn available_moves(&mut self) -> Vec<(TileIndex, Position)> {
-        let mut moves = vec![];
-
-        for x in 0..3 {
-            for y in 0..3 {
-                let index = (x, y);
-
-                for &pos in POSITIONS.iter() {
-                    if x == 1 && pos.is_vertical() {
-                        continue;
-                    }
-
-                    if y == 1 && pos.is_horizontal() {
-                        continue;
-                    }
+    /// Whether drawing `edge` completes at least one box.
+    pub fn completes_box(&self, edge: Edge) -> bool {
+        edge.tiles(self.size())
+            .into_iter()
+            .flatten()
+            .any(|(index, _)| self.bits.marked_count(index) == 3)
+    }
 
-                    if self.get_tile(index).borrow().is_open(pos) {
-                        moves.push((index, pos));
-                    }
-                }
-            }
-        }
+    /// Whether drawing `edge` gives no box its third side.
+    pub fn is_safe(&self, edge: Edge) -> bool {
+        let (index, pos) = edge.to_tile(self.size());
+        !self.will_make_end(index, pos)
+    }
 
-        moves
+    pub fn available_moves(&self) -> Vec<Edge> {
+        (0..self.bits.line_count())
+            .filter(|&line| !self.bits.is_drawn(line))
+            .map(|line| Edge::from_line(line, self.size()))
+            .collect()
     }
 
-    fn will_make_end(&mut self, mark_index: TileIndex, mark_pos: Position) -> bool {
+    fn will_make_end(&self, mark_index: TileIndex, mark_pos: Position) -> bool {
         let tile = self.get_tile(mark_index);
-        let tile_ref = tile.borrow();
 
-        if tile_ref.is_path() && tile_ref.is_open(mark_pos) {
+        if tile.is_path() && tile.is_open(mark_pos) {
             return true;
         }
 
-        if tile_ref.has_neighbor(mark_pos) {
-            let neighbor = self.get_tile(tile_ref.at_unchecked(mark_pos));
-            let neighbor_ref = neighbor.borrow();
+        if tile.has_neighbor(mark_pos) {
+            let neighbor = self.get_tile(tile.at_unchecked(mark_pos));
 
-            if neighbor_ref.is_path() && neighbor_ref.is_open(mark_pos.invert()) {
+            if neighbor.is_path() && neighbor.is_open(mark_pos.invert()) {
                 return true;
             }
         }
@@ -338,30 +226,42 @@ impl Board {
     }
 
     pub fn print(&self, sq: &Matrix<Option<Player>>) {
-        for i in 0..3 {
-            println!(
-                "+{}+{}+{}+",
-                h_line(!self.tiles[i][0].borrow().is_open(Position::Top)),
-                h_line(!self.tiles[i][1].borrow().is_open(Position::Top)),
-                h_line(!self.tiles[i][2].borrow().is_open(Position::Top))
-            );
-            println!(
-                "{} {} {} {} {} {} {}",
-                v_line(!self.tiles[i][0].borrow().is_open(Position::Left)),
-                Self::sq_to_str(sq.get(i).unwrap().get(0).unwrap()),
-                v_line(!self.tiles[i][1].borrow().is_open(Position::Left)),
-                Self::sq_to_str(sq.get(i).unwrap().get(1).unwrap()),
-                v_line(!self.tiles[i][2].borrow().is_open(Position::Left)),
-                Self::sq_to_str(sq.get(i).unwrap().get(2).unwrap()),
-                v_line(!self.tiles[i][2].borrow().is_open(Position::Right)),
-            );
+        print!("{}", self.render(|x, y| Self::sq_to_str(&sq[x][y])));
+    }
+
+    fn render<'a>(&self, label: impl Fn(usize, usize) -> &'a str) -> String {
+        let mut out = String::new();
+
+        for i in 0..self.rows() {
+            out.push('+');
+            for j in 0..self.cols() {
+                out.push_str(h_line(self.bits.is_marked((i, j), Position::Top)));
+                out.push('+');
+            }
+            out.push('\n');
+
+            for j in 0..self.cols() {
+                out.push_str(v_line(self.bits.is_marked((i, j), Position::Left)));
+                out.push(' ');
+                out.push_str(label(i, j));
+                out.push(' ');
+            }
+            out.push_str(v_line(
+                self.bits.is_marked((i, self.cols() - 1), Position::Right),
+            ));
+            out.push('\n');
         }
-        println!(
-            "+{}+{}+{}+",
-            h_line(!self.tiles[2][0].borrow().is_open(Position::Bottom)),
-            h_line(!self.tiles[2][1].borrow().is_open(Position::Bottom)),
-            h_line(!self.tiles[2][2].borrow().is_open(Position::Bottom))
-        );
+
+        out.push('+');
+        for j in 0..self.cols() {
+            out.push_str(h_line(
+                self.bits.is_marked((self.rows() - 1, j), Position::Bottom),
+            ));
+            out.push('+');
+        }
+        out.push('\n');
+
+        out
     }
 
     fn sq_to_str(sq: &Option<Player>) -> &str {
@@ -377,79 +277,41 @@ impl Board {
     }
 }
 
-impl Clone for Board {
-    fn clone(&self) -> Board {
-        let mut tiles = Vec::new();
-        for x in 0..3 {
-            let mut row = Vec::new();
-
-            for y in 0..3 {
-                let tile = &self.tiles[x][y];
-                row.push(Rc::new(RefCell::new((*tile.borrow()).clone())));
-            }
-
-            tiles.push(row);
-        }
-
-        Board { tiles }
+impl Default for Board {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
 impl Display for Board {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for i in 0..3 {
-            writeln!(
-                f,
-                "+{}+{}+{}+",
-                h_line(!self.tiles[i][0].borrow().is_open(Position::Top)),
-                h_line(!self.tiles[i][1].borrow().is_open(Position::Top)),
-                h_line(!self.tiles[i][2].borrow().is_open(Position::Top))
-            )?;
-            writeln!(
-                f,
-                "{} {} {} {} {} {} {}",
-                v_line(!self.tiles[i][0].borrow().is_open(Position::Left)),
-                " ",
-                v_line(!self.tiles[i][1].borrow().is_open(Position::Left)),
-                " ",
-                v_line(!self.tiles[i][2].borrow().is_open(Position::Left)),
-                " ",
-                v_line(!self.tiles[i][2].borrow().is_open(Position::Right)),
-            )?;
-        }
-        writeln!(
-            f,
-            "+{}+{}+{}+",
-            h_line(!self.tiles[2][0].borrow().is_open(Position::Bottom)),
-            h_line(!self.tiles[2][1].borrow().is_open(Position::Bottom)),
-            h_line(!self.tiles[2][2].borrow().is_open(Position::Bottom))
-        )?;
-
-        Ok(())
+        write!(f, "{}", self.render(|_, _| " "))
     }
 }
 
-fn h_line(cond: bool) -> String {
+fn h_line(cond: bool) -> &'static str {
     if cond {
-        "---".into()
+        "---"
     } else {
-        "   ".into()
+        "   "
     }
 }
 
-fn v_line(cond: bool) -> String {
+fn v_line(cond: bool) -> &'static str {
     if cond {
-        "|".into()
+        "|"
     } else {
-        " ".into()
+        " "
     }
 }
-
 #[cfg(test)]
 mod tests {
     use crate::{
-        board::{Game, Player},
-        tile::{Position, BOTTOM_RIGHT, CENTER, MIDDLE_LEFT, TOP_CENTER, TOP_LEFT},
+        board::{ControlAnalysis, Game, MoveError, Parity, Player},
+        edge::Edge,
+        rng::Rng,
+        tile::{ChainEnd, Position, BOTTOM_RIGHT, CENTER, MIDDLE_LEFT, TOP_CENTER, TOP_LEFT},
+        zobrist,
     };
 
     use super::Board;
@@ -476,27 +338,93 @@ mod tests {
         println!("{}", board);
     }
 
+    fn board_with(rows: usize, cols: usize, edges: &[Edge]) -> Board {
+        let mut board = Board::with_size(rows, cols);
+        for &edge in edges {
+            board.mark_for(edge, Player::Odd);
+        }
+        board
+    }
+
+    /// Length and sorted ends of every chain, shortest first.
+    fn chain_summary(board: &Board) -> Vec<(usize, [ChainEnd; 2])> {
+        let mut chains: Vec<_> = board
+            .get_chains()
+            .iter()
+            .map(|chain| {
+                let mut ends = chain.ends();
+                ends.sort();
+                (chain.len(), ends)
+            })
+            .collect();
+        chains.sort();
+        chains
+    }
+
+    fn loop_summary(board: &Board) -> Vec<usize> {
+        let mut loops: Vec<_> = board.get_loops().iter().map(|l| l.len()).collect();
+        loops.sort();
+        loops
+    }
+
+    #[test]
+    fn tiles() {
+        let mut board = Board::with_size(1, 1);
+        assert!(!board.get_tile((0, 0)).can_be_chained());
+
+        board.mark((0, 0), Position::Top);
+        assert!(!board.get_tile((0, 0)).can_be_chained());
+
+        board.mark((0, 0), Position::Left);
+        assert!(board.get_tile((0, 0)).is_path());
+
+        board.mark((0, 0), Position::Right);
+        assert!(board.get_tile((0, 0)).is_end());
+        assert_eq!(board.get_tile((0, 0)).openings(), vec![Position::Bottom]);
+    }
+
     #[test]
     fn chain() {
         let mut game = Game::new();
-        game.play((0, 0), Position::Top);
-        game.play((0, 0), Position::Bottom);
-        game.play((0, 1), Position::Bottom);
-        game.play((0, 1), Position::Right);
-        game.play((0, 2), Position::Right);
-        game.play((1, 0), Position::Left);
-        game.play((1, 1), Position::Bottom);
-        game.play((1, 2), Position::Bottom);
-        game.play((1, 2), Position::Right);
-        game.play((2, 0), Position::Right);
-        game.play((2, 0), Position::Left);
-        game.play((2, 2), Position::Bottom);
+        game.play(((0, 0), Position::Top));
+        game.play(((0, 0), Position::Bottom));
+        game.play(((0, 1), Position::Bottom));
+        game.play(((0, 1), Position::Right));
+        game.play(((0, 2), Position::Right));
+        game.play(((1, 0), Position::Left));
+        game.play(((1, 1), Position::Bottom));
+        game.play(((1, 2), Position::Bottom));
+        game.play(((1, 2), Position::Right));
+        game.play(((2, 0), Position::Right));
+        game.play(((2, 0), Position::Left));
+        game.play(((2, 2), Position::Bottom));
         game.print_board();
 
-        // println!("Chains = {}", board.get_chains().len());
+        // Every box has two sides drawn: the top left pair, the snake from
+        // the top right to the bottom left and the bottom right pair
+        let border = [ChainEnd::Border, ChainEnd::Border];
+        assert_eq!(
+            chain_summary(game.board()),
+            vec![(2, border), (2, border), (5, border)]
+        );
+        assert!(loop_summary(game.board()).is_empty());
+
+        let snake = game
+            .board()
+            .get_chains()
+            .into_iter()
+            .find(|chain| chain.is_long())
+            .unwrap();
+        let indices: Vec<_> = snake.tiles().iter().map(|tile| tile.index()).collect();
+        let mut expected = vec![(0, 2), (1, 2), (1, 1), (1, 0), (2, 0)];
+        if indices[0] != expected[0] {
+            expected.reverse();
+        }
+        assert_eq!(indices, expected);
+        assert!(snake.is_open());
 
-        println!("U(Even) = {}", game.utility(Player::Even));
-        println!("U(Odd) = {}", game.utility(Player::Odd));
+        assert_eq!(game.utility(Player::Even), 0);
+        assert_eq!(game.utility(Player::Odd), 0);
     }
 
     #[test]
@@ -514,55 +442,577 @@ mod tests {
         board.mark(BOTTOM_RIGHT, Position::Right);
         board.mark(BOTTOM_RIGHT, Position::Bottom);
         println!("{}", board);
-        println!();
-        println!();
 
-        println!("Loops = {}", board.get_loops().len());
+        assert_eq!(loop_summary(&board), vec![4]);
+        assert!(!board.get_loops()[0].is_long());
+
+        // The bottom right box opens onto the boxes above and left of it
+        assert_eq!(
+            chain_summary(&board),
+            vec![(1, [ChainEnd::Open((1, 2)), ChainEnd::Open((2, 1))])]
+        );
+    }
+
+    #[test]
+    fn chain_ends() {
+        let strip: Vec<Edge> = (0..4)
+            .flat_map(|col| [Edge::horizontal(0, col), Edge::horizontal(1, col)])
+            .collect();
+
+        let mut board = board_with(1, 4, &strip);
+        assert_eq!(chain_summary(&board), vec![(4, [ChainEnd::Border; 2])]);
+        assert!(board.get_chains()[0].is_open());
+
+        board.mark_for(Edge::vertical(0, 0), Player::Odd);
+        assert_eq!(
+            chain_summary(&board),
+            vec![(4, [ChainEnd::Closed, ChainEnd::Border])]
+        );
+        assert!(board.get_chains()[0].is_half_open());
+
+        board.mark_for(Edge::vertical(0, 4), Player::Odd);
+        assert_eq!(chain_summary(&board), vec![(4, [ChainEnd::Closed; 2])]);
+        assert!(board.get_chains()[0].is_closed());
+
+        // Two boxes leading from the border into an undrawn box
+        let board = board_with(1, 3, &strip[..4]);
+        assert_eq!(
+            chain_summary(&board),
+            vec![(2, [ChainEnd::Border, ChainEnd::Open((0, 2))])]
+        );
+
+        // Single boxes
+        let board = board_with(1, 1, &[Edge::horizontal(0, 0), Edge::horizontal(1, 0)]);
+        assert_eq!(chain_summary(&board), vec![(1, [ChainEnd::Border; 2])]);
+
+        let board = board_with(
+            1,
+            1,
+            &[
+                Edge::horizontal(0, 0),
+                Edge::horizontal(1, 0),
+                Edge::vertical(0, 0),
+            ],
+        );
+        assert_eq!(
+            chain_summary(&board),
+            vec![(1, [ChainEnd::Closed, ChainEnd::Border])]
+        );
+
+        // Two boxes that can both be taken by the line between them
+        let board = board_with(
+            1,
+            2,
+            &[
+                Edge::horizontal(0, 0),
+                Edge::horizontal(0, 1),
+                Edge::horizontal(1, 0),
+                Edge::horizontal(1, 1),
+                Edge::vertical(0, 0),
+                Edge::vertical(0, 2),
+            ],
+        );
+        assert_eq!(chain_summary(&board), vec![(2, [ChainEnd::Closed; 2])]);
+
+        // Nothing is drawn, nothing is chained
+        assert!(chain_summary(&Board::new()).is_empty());
+        assert!(loop_summary(&Board::new()).is_empty());
+    }
+
+    #[test]
+    fn ring() {
+        // The eight outer boxes of a 3x3 board around a taken center
+        let mut edges: Vec<Edge> = (0..3)
+            .flat_map(|i| {
+                [
+                    Edge::horizontal(0, i),
+                    Edge::horizontal(3, i),
+                    Edge::vertical(i, 0),
+                    Edge::vertical(i, 3),
+                ]
+            })
+            .collect();
+        edges.extend([
+            Edge::horizontal(1, 1),
+            Edge::horizontal(2, 1),
+            Edge::vertical(1, 1),
+            Edge::vertical(1, 2),
+        ]);
+        let board = board_with(3, 3, &edges);
+
+        assert_eq!(loop_summary(&board), vec![8]);
+        assert!(board.get_loops()[0].is_long());
+        assert!(!board.get_loops()[0].contains(CENTER));
+        assert!(chain_summary(&board).is_empty());
+
+        // Cutting the ring turns it into a chain closed at the cut
+        let mut board = board;
+        board.mark_for(Edge::vertical(0, 1), Player::Odd);
+        assert_eq!(chain_summary(&board), vec![(8, [ChainEnd::Closed; 2])]);
+        assert!(loop_summary(&board).is_empty());
+    }
+
+    #[test]
+    fn chains_partition_boxes() {
+        let mut rng = Rng::new(13);
+
+        for &(rows, cols) in [(1, 1), (1, 4), (2, 2), (2, 3), (3, 3), (4, 4), (5, 5)].iter() {
+            for _ in 0..50 {
+                let mut board = Board::with_size(rows, cols);
+                let lines = board.available_moves().len();
+                for _ in 0..rng.below(lines + 1) {
+                    let moves = board.available_moves();
+                    board.mark_for(*rng.choose(&moves).unwrap(), Player::Odd);
+                }
+
+                let (chains, loops) = board.chains_and_loops();
+
+                for x in 0..rows {
+                    for y in 0..cols {
+                        let tile = board.get_tile((x, y));
+                        let owners = chains.iter().filter(|chain| chain.contains((x, y))).count()
+                            + loops.iter().filter(|l| l.contains((x, y))).count();
+
+                        assert_eq!(owners, tile.can_be_chained() as usize, "{}", board);
+                    }
+                }
+
+                for chain in chains.iter() {
+                    let tiles = chain.tiles();
+                    for pair in tiles.windows(2) {
+                        assert!(pair[0].connected_to(&pair[1]), "{}", board);
+                    }
+
+                    for end in chain.ends() {
+                        if let ChainEnd::Open(index) = end {
+                            let junction = board.get_tile(index);
+                            assert!(junction.openings().len() >= 3, "{}", board);
+                        }
+                    }
+
+                    let closed = tiles.iter().filter(|tile| tile.is_end()).count();
+                    let closed_ends = chain
+                        .ends()
+                        .iter()
+                        .filter(|&&end| end == ChainEnd::Closed)
+                        .count();
+                    assert_eq!(closed, closed_ends.min(tiles.len()), "{}", board);
+                }
+
+                for l in loops.iter() {
+                    let tiles = l.tiles();
+                    assert!(tiles.iter().all(|tile| tile.is_path()));
+                    assert!(tiles[0].connected_to(&tiles[tiles.len() - 1]));
+                    for pair in tiles.windows(2) {
+                        assert!(pair[0].connected_to(&pair[1]), "{}", board);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn control_analysis() {
+        let analysis = Game::new().control_analysis();
+        assert_eq!((analysis.dots, analysis.long_chains), (16, 0));
+        assert_eq!(analysis.leader, Player::Odd);
+        assert_eq!(analysis.controlled_value, None);
+
+        // A single chain of four: 10 dots + 1 long chain is odd
+        let mut game = Game::with_size(1, 4);
+        for col in 0..4 {
+            game.play(Edge::horizontal(0, col));
+            game.play(Edge::horizontal(1, col));
+        }
+        let analysis = game.control_analysis();
+        assert_eq!(analysis.long_chains, 1);
+        assert_eq!(analysis.leader, Player::Even);
+        assert_eq!(ControlAnalysis::target(Player::Even), Parity::Odd);
+        assert_eq!(analysis.controller, Some(Player::Even));
+        assert_eq!(analysis.controlled_value, Some(4));
+
+        // Opening the chain leaves something to take
+        game.play(Edge::vertical(0, 0));
+        assert_eq!(game.control_analysis().controlled_value, None);
+
+        // Two chains of three: the controller gives two boxes away to keep
+        // control and takes the last chain whole
+        let mut game = Game::with_size(2, 3);
+        for row in 0..3 {
+            for col in 0..3 {
+                game.play(Edge::horizontal(row, col));
+            }
+        }
+        let analysis = game.control_analysis();
+        assert_eq!((analysis.dots, analysis.long_chains), (12, 2));
+        assert_eq!(analysis.leader, Player::Odd);
+        assert_eq!(analysis.controller, Some(Player::Odd));
+        assert_eq!(analysis.controlled_value, Some(2));
+
+        // A loop of eight around a taken center
+        let mut game = Game::new();
+        for i in 0..3 {
+            game.play(Edge::horizontal(0, i));
+            game.play(Edge::horizontal(3, i));
+            game.play(Edge::vertical(i, 0));
+            game.play(Edge::vertical(i, 3));
+        }
+        game.play(Edge::horizontal(1, 1));
+        game.play(Edge::horizontal(2, 1));
+        game.play(Edge::vertical(1, 1));
+        game.play(Edge::vertical(1, 2));
+        let analysis = game.control_analysis();
+        assert_eq!((analysis.long_chains, analysis.loops), (0, 1));
+        assert_eq!(analysis.controller, Some(game.player_to_play().opponent()));
+        assert_eq!(analysis.controlled_value, Some(8));
+
+        // Short chains are not a controlled endgame
+        let mut game = Game::with_size(1, 2);
+        for col in 0..2 {
+            game.play(Edge::horizontal(0, col));
+            game.play(Edge::horizontal(1, col));
+        }
+        let analysis = game.control_analysis();
+        assert_eq!(analysis.long_chains, 0);
+        assert_eq!(analysis.controlled_value, None);
+    }
+
+    #[test]
+    fn sizes() {
+        // (rows + 1) * cols horizontal lines and rows * (cols + 1) vertical lines
+        for &(rows, cols) in [(1, 1), (1, 4), (2, 3), (3, 3), (4, 2), (5, 5)].iter() {
+            let board = Board::with_size(rows, cols);
+            let lines = (rows + 1) * cols + rows * (cols + 1);
+
+            assert_eq!(board.size(), (rows, cols));
+            assert_eq!(board.available_moves().len(), lines);
+        }
+
+        println!("{}", Board::with_size(1, 4));
+        println!("{}", Board::with_size(5, 5));
+    }
+
+    #[test]
+    fn play_to_end() {
+        for &(rows, cols) in [(1, 1), (1, 5), (2, 3), (5, 5)].iter() {
+            let mut game = Game::with_size(rows, cols);
+            let mut moves = 0;
+
+            while !game.ended() {
+                game.play(game.available_moves()[0]);
+                moves += 1;
+            }
+
+            assert_eq!(moves, (rows + 1) * cols + rows * (cols + 1));
+            assert!(game.available_moves().is_empty());
+            assert_eq!(
+                game.utility(Player::Odd) + game.utility(Player::Even),
+                0,
+                "utility must be zero-sum"
+            );
+            assert_eq!(game.evaluate(Player::Odd), game.utility(Player::Odd));
+            game.print_board();
+        }
+    }
+
+    #[test]
+    fn evaluate() {
+        let mut game = Game::with_size(3, 3);
+
+        for edge in game.available_moves().into_iter().step_by(3) {
+            game.play(edge);
+            assert_eq!(game.evaluate(Player::Odd), -game.evaluate(Player::Even));
+        }
+    }
+
+    #[test]
+    fn try_play() {
+        let mut game = Game::with_size(1, 2);
+
+        let outcome = game.try_play(((0, 0), Position::Top)).unwrap();
+        assert_eq!(outcome.player(), Player::Odd);
+        assert!(outcome.completed().is_empty());
+        assert!(!outcome.extra_turn());
+        assert_eq!(game.player_to_play(), Player::Even);
+
+        assert_eq!(
+            game.try_play(Edge::horizontal(0, 0)),
+            Err(MoveError::LineAlreadyDrawn(Edge::horizontal(0, 0)))
+        );
+        assert_eq!(
+            game.try_play(Edge::vertical(0, 3)),
+            Err(MoveError::OutOfBounds(Edge::vertical(0, 3)))
+        );
+        assert_eq!(
+            game.try_play(((5, 5), Position::Top)),
+            Err(MoveError::OutOfBounds(Edge::horizontal(5, 5)))
+        );
+        assert_eq!(game.player_to_play(), Player::Even);
+
+        game.play(((0, 0), Position::Left));
+        game.play(((0, 0), Position::Bottom));
+        game.play(((0, 1), Position::Top));
+        game.play(((0, 1), Position::Right));
+        game.play(((0, 1), Position::Bottom));
+
+        let turn = game.player_to_play();
+        let outcome = game.try_play(((0, 0), Position::Right)).unwrap();
+        assert_eq!(outcome.player(), turn);
+        assert_eq!(outcome.completed(), &[(0, 0), (0, 1)]);
+        assert!(outcome.extra_turn());
+        assert_eq!(game.player_to_play(), turn);
+        assert_eq!(game.utility(turn), 2);
+
+        assert!(game.ended());
+        assert_eq!(
+            game.try_play(Edge::vertical(0, 1)),
+            Err(MoveError::GameOver)
+        );
+    }
+
+    #[test]
+    fn undo_redo() {
+        let mut game = Game::with_size(2, 2);
+        let mut snapshots = vec![(game.board, game.player_to_play())];
+
+        // Ends with two double captures
+        let moves = [
+            Edge::horizontal(0, 0),
+            Edge::horizontal(0, 1),
+            Edge::vertical(0, 0),
+            Edge::vertical(0, 2),
+            Edge::horizontal(2, 0),
+            Edge::horizontal(2, 1),
+            Edge::vertical(1, 0),
+            Edge::vertical(1, 2),
+            Edge::horizontal(1, 0),
+            Edge::horizontal(1, 1),
+            Edge::vertical(0, 1),
+            Edge::vertical(1, 1),
+        ];
+        for &edge in moves.iter() {
+            game.play(edge);
+            snapshots.push((game.board, game.player_to_play()));
+        }
+
+        assert!(game.ended());
+        assert_eq!(game.history().len(), moves.len());
+        assert_eq!(game.history()[10].completed(), &[(0, 0), (0, 1)]);
+        assert!(game.history()[10].extra_turn());
+        assert_eq!(game.history()[11].player(), game.history()[10].player());
+
+        for i in (0..moves.len()).rev() {
+            let outcome = game.undo().unwrap();
+            assert_eq!(outcome.edge(), moves[i]);
+            assert_eq!((game.board, game.player_to_play()), snapshots[i]);
+        }
+        assert!(game.undo().is_none());
+        assert!(!game.ended());
+
+        for (i, &edge) in moves.iter().enumerate() {
+            assert_eq!(game.redo().unwrap().edge(), edge);
+            assert_eq!((game.board, game.player_to_play()), snapshots[i + 1]);
+        }
+        assert!(game.redo().is_none());
+
+        // Playing a new move drops the moves that were taken back
+        game.undo();
+        game.undo();
+        game.play(Edge::vertical(1, 1));
+        assert!(!game.can_redo());
+        assert_eq!(game.history().len(), moves.len() - 1);
+    }
+
+    #[test]
+    fn hash_key() {
+        let mut game = Game::with_size(2, 2);
+        let empty = game.hash_key();
+
+        // Lines commute, so both orders reach the same position
+        let mut first = game.clone();
+        first.play(Edge::horizontal(0, 0));
+        first.play(Edge::vertical(1, 2));
+        let mut second = game.clone();
+        second.play(Edge::vertical(1, 2));
+        second.play(Edge::horizontal(0, 0));
+        assert_eq!(first.hash_key(), second.hash_key());
+        assert!(first == second);
+
+        // Same lines with a different player to play
+        let mut third = game.clone();
+        third.play(Edge::horizontal(0, 0));
+        assert_ne!(third.position_key(), game.position_key());
+        third.play(Edge::vertical(1, 2));
+        third.play(Edge::vertical(0, 0));
+        third.undo();
+        assert_eq!(third.hash_key(), first.hash_key());
+
+        for edge in [
+            Edge::horizontal(0, 0),
+            Edge::vertical(0, 0),
+            Edge::vertical(0, 1),
+            Edge::horizontal(1, 0),
+        ] {
+            game.play(edge);
+            let key = zobrist::position_key(game.board.bits(), game.player_to_play());
+            assert_eq!(game.position_key(), key);
+        }
+
+        // The box acquired by the last move is part of the full key only
+        assert_eq!(game.score_difference(), -1);
+        assert_eq!(
+            game.hash_key(),
+            game.position_key() ^ zobrist::score_key(-1)
+        );
+        assert_ne!(zobrist::score_key(-1), zobrist::score_key(1));
+
+        while game.undo().is_some() {}
+        assert_eq!(game.hash_key(), empty);
     }
 }
 
+#[derive(Debug, Clone)]
 pub struct Game {
     board: Board,
     turn: Player,
-    squares: Matrix<Option<Player>>,
+    history: Vec<MoveOutcome>,
+    undone: Vec<MoveOutcome>,
+    key: u64,
 }
 
 impl Game {
     pub fn new() -> Self {
+        Self::with_size(3, 3)
+    }
+
+    /// Create a new game on an empty board of `rows` × `cols` boxes.
+    pub fn with_size(rows: usize, cols: usize) -> Self {
         Self {
-            board: Board::new(),
+            board: Board::with_size(rows, cols),
             turn: Player::Odd,
-            squares: vec![
-                vec![None, None, None],
-                vec![None, None, None],
-                vec![None, None, None],
-            ],
+            history: vec![],
+            undone: vec![],
+            key: zobrist::side_key(Player::Odd),
         }
     }
 
-    pub fn play(&mut self, index: TileIndex, pos: Position) {
-        self.board.mark(index, pos);
+    pub fn size(&self) -> BoardSize {
+        self.board.size()
+    }
 
-        let acquired_squares = self.board.acquisitions();
-        let mut switching = true;
+    /// Play a move, panicking if it is not legal. See [`Game::try_play`].
+    pub fn play(&mut self, edge: impl Into<Edge>) {
+        if let Err(err) = self.try_play(edge) {
+            panic!("{}", err)
+        }
+    }
 
-        for x in 0..3 {
-            for y in 0..3 {
-                if let None = self.squares[x][y] {
-                    if acquired_squares[x][y] {
-                        self.squares[x][y] = Some(self.turn);
-                        switching = false;
-                    }
-                }
-            }
+    /// Play a move for the player to play, reporting the boxes it completed
+    /// and whether that player moves again.
+    pub fn try_play(&mut self, edge: impl Into<Edge>) -> Result<MoveOutcome, MoveError> {
+        let edge = edge.into();
+
+        if self.ended() {
+            return Err(MoveError::GameOver);
         }
 
-        if switching {
+        if !edge.is_on_board(self.size()) {
+            return Err(MoveError::OutOfBounds(edge));
+        }
+
+        if self.board.is_drawn(edge) {
+            return Err(MoveError::LineAlreadyDrawn(edge));
+        }
+
+        // A new move starts a new line of play
+        self.undone.clear();
+
+        Ok(self.apply(edge))
+    }
+
+    fn apply(&mut self, edge: Edge) -> MoveOutcome {
+        let player = self.turn;
+        let completed = self.board.mark_for(edge, player);
+        let extra_turn = !completed.is_empty();
+
+        if !extra_turn {
             self.switch();
         }
+
+        self.key ^= zobrist::line_key(edge.line(self.size()))
+            ^ zobrist::side_key(player)
+            ^ zobrist::side_key(self.turn);
+
+        let outcome = MoveOutcome {
+            edge,
+            player,
+            completed,
+            extra_turn,
+        };
+        self.history.push(outcome.clone());
+
+        outcome
     }
 
-    pub fn available_moves(&mut self) -> Vec<(TileIndex, Position)> {
+    /// Take back the last move, restoring the board, the acquired boxes and
+    /// the player to play. Returns the move that was taken back.
+    pub fn undo(&mut self) -> Option<MoveOutcome> {
+        let outcome = self.history.pop()?;
+
+        self.board.unmark(outcome.edge);
+        self.key ^= zobrist::line_key(outcome.edge.line(self.size()))
+            ^ zobrist::side_key(self.turn)
+            ^ zobrist::side_key(outcome.player);
+        self.turn = outcome.player;
+        self.undone.push(outcome.clone());
+
+        Some(outcome)
+    }
+
+    /// Play again the last move taken back by [`Game::undo`].
+    pub fn redo(&mut self) -> Option<MoveOutcome> {
+        let edge = self.undone.pop()?.edge;
+
+        Some(self.apply(edge))
+    }
+
+    /// Every move played so far, oldest first.
+    pub fn history(&self) -> &[MoveOutcome] {
+        &self.history
+    }
+
+    /// Zobrist key of the drawn lines, the player to play and the score
+    /// difference. Two games with the same key are, with overwhelming
+    /// probability, the same position with the same score.
+    pub fn hash_key(&self) -> u64 {
+        self.key ^ zobrist::score_key(self.score_difference())
+    }
+
+    /// Zobrist key of the drawn lines and the player to play only, for
+    /// callers that only care about how the rest of the game can go.
+    pub fn position_key(&self) -> u64 {
+        self.key
+    }
+
+    /// Boxes acquired by [`Player::Odd`] minus boxes acquired by
+    /// [`Player::Even`].
+    pub fn score_difference(&self) -> i32 {
+        self.acquired_squares(Player::Odd) - self.acquired_squares(Player::Even)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.undone.is_empty()
+    }
+
+    pub fn is_legal(&self, edge: Edge) -> bool {
+        !self.ended() && edge.is_on_board(self.size()) && !self.board.is_drawn(edge)
+    }
+
+    pub fn available_moves(&self) -> Vec<Edge> {
         self.board.available_moves()
     }
 
@@ -571,81 +1021,96 @@ impl Game {
     }
 
     // Calculate board setup utility value on certain player perspective
-    pub fn utility(&mut self, player: Player) -> i32 {
-        // let chains = self.board.get_chains();
-        // let loops = self.board.get_loops();
-
-        // let mut chain_values = 0;
-        // let this_player_to_move = self.turn == player;
-
-        // let safe_moves = self.board.safe_moves_count() as u32;
-        // let factor = -1_i32.pow(safe_moves + 1) * {
-        //     if this_player_to_move {
-        //         1
-        //     } else {
-        //         -1
-        //     }
-        // };
-
-        // let chains_count = chains.len();
-        // let double_cross_compensation = if chains_count > 1 { 4 } else { 0 };
-        // // TODO: Get full value of last chain
-        // for chain in chains.iter() {
-        //     if !chain.is_long() {
-        //         // 2-chain
-        //         if chain.is_closed() {
-        //             chain_values += 2;
-        //         } else {
-        //             chain_values -= 2;
-        //         }
-        //     } else {
-        //         // long chain
-        //         if chain.is_closed() {
-        //             chain_values += chain.len() as i32;
-        //         } else if chain.is_half_open() {
-        //             chain_values += chain.len() as i32 - double_cross_compensation;
-        //         } else {
-        //             chain_values -= double_cross_compensation - chain.len() as i32;
-        //         }
-        //     }
-        // }
-
-        // let mut loop_values = 0;
-        // for _loop in loops.iter() {
-        //     loop_values -= _loop.len() as i32;
-        // }
-
+    pub fn utility(&self, player: Player) -> i32 {
         self.acquired_squares(player) - self.acquired_squares(player.opponent())
-        // + (chain_values + loop_values + self.board.free_edge_squares()) * factor
     }
 
-    fn acquired_squares(&self, player: Player) -> i32 {
-        let mut s = 0;
-        for row in self.squares.iter() {
-            for &sq in row.iter() {
-                if let Some(p) = sq {
-                    if p == player {
-                        s += 1;
-                    }
-                }
-            }
+    /// Estimate the final box difference on `player` perspective without
+    /// searching: the boxes acquired so far, the free boxes the player to
+    /// move can take, and the chains and loops the player in control is
+    /// expected to collect.
+    pub fn evaluate(&self, player: Player) -> i32 {
+        let utility = self.utility(player);
+
+        if self.ended() {
+            return utility;
         }
 
-        s
+        let mover = if self.turn == player { 1 } else { -1 };
+
+        // Safe moves alternate, so with an even number of them left the
+        // player to move is the first to run out and has to open a chain
+        let safe_moves = self.board.safe_moves_count();
+        let controller = if safe_moves % 2 == 0 { -mover } else { mover };
+
+        let (chains, loops) = self.board.chains_and_loops();
+        let controlled = controlled_value(&chains, &loops);
+
+        utility + mover * self.board.free_edge_squares() + controller * controlled
     }
 
-    pub fn ended(&self) -> bool {
-        let mut acquired_squares = 0;
+    /// Where the position stands by the long chain rule and, once only long
+    /// chains and loops are left, what control of them is worth.
+    pub fn control_analysis(&self) -> ControlAnalysis {
+        let (chains, loops) = self.board.chains_and_loops();
+        let dots = (self.board.rows() + 1) * (self.board.cols() + 1);
+        let long_chains = chains.iter().filter(|chain| chain.is_long()).count();
 
-        for row in self.squares.iter() {
-            for &sq in row.iter() {
-                if let Some(_) = sq {
-                    acquired_squares += 1;
-                }
-            }
+        let parity = Parity::of(dots + long_chains);
+        let leader = if ControlAnalysis::target(Player::Odd) == parity {
+            Player::Odd
+        } else {
+            Player::Even
+        };
+
+        // Every box left is in a long chain or a loop that nobody can take
+        // from yet, so the player to move has to open one of them
+        let bits = self.board.bits();
+        let chained: usize = chains.iter().map(|chain| chain.len()).sum::<usize>()
+            + loops.iter().map(|l| l.len()).sum::<usize>();
+        let remaining = bits.box_count()
+            - (bits.owned_count(Player::Odd) + bits.owned_count(Player::Even)) as usize;
+        let endgame = !self.ended()
+            && chained == remaining
+            && chains
+                .iter()
+                .all(|chain| chain.is_long() && chain.is_open());
+
+        let (controller, controlled_value) = if endgame {
+            (
+                Some(self.turn.opponent()),
+                Some(controlled_value(&chains, &loops)),
+            )
+        } else {
+            (None, None)
+        };
+
+        ControlAnalysis {
+            dots,
+            long_chains,
+            loops: loops.len(),
+            leader,
+            controller,
+            controlled_value,
         }
+    }
 
-        acquired_squares == 9
+    fn acquired_squares(&self, player: Player) -> i32 {
+        self.board.bits().owned_count(player) as i32
+    }
+
+    pub fn ended(&self) -> bool {
+        let bits = self.board.bits();
+        (bits.owned_count(Player::Odd) + bits.owned_count(Player::Even)) as usize
+            == bits.box_count()
+    }
+
+    pub fn board(&self) -> &Board {
+        &self.board
+    }
+
+    pub fn squares(&self) -> Matrix<Option<Player>> {
+        self.board.owners()
     }
 
     fn switch(&mut self) {
@@ -659,31 +1124,159 @@ impl Game {
     }
 
     pub fn print_board_without_pad(&self) {
-        self.board.print(&self.squares);
+        self.board.print(&self.squares());
     }
 }
 
-impl Clone for Game {
-    fn clone(&self) -> Self {
-        let mut squares = Vec::new();
-        for x in 0..3 {
-            let mut row = Vec::new();
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-            for y in 0..3 {
-                row.push(self.squares[x][y].clone());
-            }
+/// Games are equal when their positions are: the same lines drawn, the same
+/// boxes acquired by the same players and the same player to play. The move
+/// history is not compared.
+impl PartialEq for Game {
+    fn eq(&self, other: &Self) -> bool {
+        self.board == other.board && self.turn == other.turn
+    }
+}
+
+impl Eq for Game {}
+
+impl Hash for Game {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash_key());
+    }
+}
+
+/// Boxes the player in control of `chains` and `loops` gets over the
+/// opponent by declining the last two boxes of every long chain and the last
+/// four of every loop to stay in control, except in the last one. Short
+/// chains are left out.
+fn controlled_value(chains: &[Chain], loops: &[Loop]) -> i32 {
+    let mut controlled = 0;
+    let mut long_chains = 0;
+    for chain in chains.iter().filter(|chain| chain.is_long()) {
+        controlled += chain.len() as i32 - 4;
+        long_chains += 1;
+    }
+
+    for _loop in loops.iter() {
+        controlled += _loop.len() as i32 - 8;
+    }
+
+    if long_chains > 0 {
+        controlled += 4;
+    } else if !loops.is_empty() {
+        controlled += 8;
+    }
 
-            squares.push(row);
+    controlled
+}
+
+/// The chain fight according to the long chain rule: the first player wants
+/// the number of dots plus the number of long chains to be even, the second
+/// player wants it odd.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ControlAnalysis {
+    /// Dots on the board, `(rows + 1) * (cols + 1)`.
+    pub dots: usize,
+    /// Chains of three boxes or more.
+    pub long_chains: usize,
+    pub loops: usize,
+    /// The player the rule currently favours, whose target parity matches
+    /// the dots plus the long chains on the board.
+    pub leader: Player,
+    /// Once only long chains and loops are left, the player not to move,
+    /// since the player to move has to open one of them.
+    pub controller: Option<Player>,
+    /// Once only long chains and loops are left, the boxes the controller
+    /// gets over the opponent from them by keeping control to the end.
+    pub controlled_value: Option<i32>,
+}
+
+impl ControlAnalysis {
+    /// Parity of dots plus long chains `player` aims for. [`Player::Odd`]
+    /// moves first.
+    pub fn target(player: Player) -> Parity {
+        match player {
+            Player::Odd => Parity::Even,
+            Player::Even => Parity::Odd,
         }
+    }
+}
 
-        Self {
-            board: self.board.clone(),
-            turn: self.turn,
-            squares,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Parity {
+    Even,
+    Odd,
+}
+
+impl Parity {
+    pub fn of(count: usize) -> Self {
+        if count.is_multiple_of(2) {
+            Self::Even
+        } else {
+            Self::Odd
         }
     }
 }
 
+/// What happened when a move was played.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MoveOutcome {
+    edge: Edge,
+    player: Player,
+    completed: Vec<TileIndex>,
+    extra_turn: bool,
+}
+
+impl MoveOutcome {
+    /// The edge that was drawn.
+    pub fn edge(&self) -> Edge {
+        self.edge
+    }
+
+    /// The player who made the move.
+    pub fn player(&self) -> Player {
+        self.player
+    }
+
+    /// Boxes completed by the move, at most two.
+    pub fn completed(&self) -> &[TileIndex] {
+        &self.completed
+    }
+
+    /// Whether the same player moves again.
+    pub fn extra_turn(&self) -> bool {
+        self.extra_turn
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoveError {
+    /// The edge does not lie on the board.
+    OutOfBounds(Edge),
+    /// The edge has already been drawn.
+    LineAlreadyDrawn(Edge),
+    /// Every box has been acquired.
+    GameOver,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::OutOfBounds(edge) => write!(f, "`{}` is not on the board", edge),
+            Self::LineAlreadyDrawn(edge) => write!(f, "`{}` has already been drawn", edge),
+            Self::GameOver => write!(f, "the game is over"),
+        }
+    }
+}
+
+impl Error for MoveError {}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Player {
     Odd,
@@ -0,0 +1,265 @@
+use std::{error::Error, fmt::Display, str::FromStr};
+
+use crate::tile::{BoardSize, Position, TileIndex};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Orientation {
+    Horizontal,
+    Vertical,
+}
+
+/// A line on the board, named independently of the tiles it borders.
+///
+/// Horizontal edges run along the top of row `row` (`row` goes up to the
+/// number of box rows, which is the bottom border), vertical edges run along
+/// the left of column `col` (`col` goes up to the number of box columns).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Edge {
+    orientation: Orientation,
+    row: usize,
+    col: usize,
+}
+
+impl Edge {
+    pub const fn new(orientation: Orientation, row: usize, col: usize) -> Self {
+        Self {
+            orientation,
+            row,
+            col,
+        }
+    }
+
+    pub const fn horizontal(row: usize, col: usize) -> Self {
+        Self::new(Orientation::Horizontal, row, col)
+    }
+
+    pub const fn vertical(row: usize, col: usize) -> Self {
+        Self::new(Orientation::Vertical, row, col)
+    }
+
+    /// The edge on the `pos` side of the tile at `index`.
+    pub fn from_tile(index: TileIndex, pos: Position) -> Self {
+        let (x, y) = index;
+
+        match pos {
+            Position::Top => Self::horizontal(x, y),
+            Position::Bottom => Self::horizontal(x + 1, y),
+            Position::Left => Self::vertical(x, y),
+            Position::Right => Self::vertical(x, y + 1),
+        }
+    }
+
+    /// Rebuild an edge from its line number on a board of the given size.
+    pub fn from_line(line: usize, size: BoardSize) -> Self {
+        let (rows, cols) = size;
+        let horizontal = (rows + 1) * cols;
+
+        if line < horizontal {
+            Self::horizontal(line / cols, line % cols)
+        } else {
+            let line = line - horizontal;
+            Self::vertical(line / (cols + 1), line % (cols + 1))
+        }
+    }
+
+    pub fn orientation(&self) -> Orientation {
+        self.orientation
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        self.orientation == Orientation::Horizontal
+    }
+
+    pub fn is_vertical(&self) -> bool {
+        self.orientation == Orientation::Vertical
+    }
+
+    pub fn is_on_board(&self, size: BoardSize) -> bool {
+        let (rows, cols) = size;
+
+        match self.orientation {
+            Orientation::Horizontal => self.row <= rows && self.col < cols,
+            Orientation::Vertical => self.row < rows && self.col <= cols,
+        }
+    }
+
+    /// Line number of this edge on a board of the given size. Horizontal
+    /// lines are numbered first, row by row, followed by the vertical lines.
+    pub fn line(&self, size: BoardSize) -> usize {
+        let (rows, cols) = size;
+
+        match self.orientation {
+            Orientation::Horizontal => self.row * cols + self.col,
+            Orientation::Vertical => (rows + 1) * cols + self.row * (cols + 1) + self.col,
+        }
+    }
+
+    /// Both tile-relative spellings of this edge: the tile above/left of it
+    /// and the tile below/right of it, if they are on the board.
+    pub fn tiles(&self, size: BoardSize) -> [Option<(TileIndex, Position)>; 2] {
+        let (rows, cols) = size;
+        let (x, y) = (self.row, self.col);
+
+        match self.orientation {
+            Orientation::Horizontal => [
+                (x > 0).then(|| ((x - 1, y), Position::Bottom)),
+                (x < rows).then_some(((x, y), Position::Top)),
+            ],
+            Orientation::Vertical => [
+                (y > 0).then(|| ((x, y - 1), Position::Right)),
+                (y < cols).then_some(((x, y), Position::Left)),
+            ],
+        }
+    }
+
+    /// The first tile-relative spelling of this edge.
+    pub fn to_tile(&self, size: BoardSize) -> (TileIndex, Position) {
+        self.tiles(size).into_iter().flatten().next().unwrap()
+    }
+}
+
+impl From<(TileIndex, Position)> for Edge {
+    fn from((index, pos): (TileIndex, Position)) -> Self {
+        Self::from_tile(index, pos)
+    }
+}
+
+/// Written as `h <row> <col>` or `v <row> <col>`.
+impl Display for Edge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let orientation = match self.orientation {
+            Orientation::Horizontal => "h",
+            Orientation::Vertical => "v",
+        };
+
+        write!(f, "{} {} {}", orientation, self.row, self.col)
+    }
+}
+
+/// Reads either the edge notation `h <row> <col>` / `v <row> <col>`, or the
+/// tile-relative notation `<row> <col> <top|bottom|left|right>`. Tokens may be
+/// separated by spaces or commas.
+impl FromStr for Edge {
+    type Err = ParseEdgeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseEdgeError {
+            input: s.trim().to_string(),
+        };
+
+        let tokens: Vec<String> = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|token| !token.is_empty())
+            .map(|token| token.to_lowercase())
+            .collect();
+
+        if tokens.len() != 3 {
+            return Err(err());
+        }
+
+        let number = |token: &str| token.parse::<usize>().map_err(|_| err());
+
+        match tokens[0].as_str() {
+            "h" => Ok(Self::horizontal(number(&tokens[1])?, number(&tokens[2])?)),
+            "v" => Ok(Self::vertical(number(&tokens[1])?, number(&tokens[2])?)),
+            _ => {
+                let index = (number(&tokens[0])?, number(&tokens[1])?);
+                let pos = match tokens[2].as_str() {
+                    "top" | "t" => Position::Top,
+                    "bottom" | "b" => Position::Bottom,
+                    "left" | "l" => Position::Left,
+                    "right" | "r" => Position::Right,
+                    _ => return Err(err()),
+                };
+
+                Ok(Self::from_tile(index, pos))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseEdgeError {
+    input: String,
+}
+
+impl Display for ParseEdgeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "`{}` is not a move, expected `h <row> <col>`, `v <row> <col>` or `<row> <col> <top|bottom|left|right>`",
+            self.input
+        )
+    }
+}
+
+impl Error for ParseEdgeError {}
+
+#[cfg(test)]
+mod tests {
+    use crate::tile::{Position, POSITIONS};
+
+    use super::Edge;
+
+    #[test]
+    fn spellings() {
+        assert_eq!(
+            Edge::from_tile((0, 0), Position::Bottom),
+            Edge::from_tile((1, 0), Position::Top)
+        );
+        assert_eq!(
+            Edge::from_tile((2, 1), Position::Right),
+            Edge::from_tile((2, 2), Position::Left)
+        );
+
+        let size = (3, 4);
+        for x in 0..3 {
+            for y in 0..4 {
+                for &pos in POSITIONS.iter() {
+                    let edge = Edge::from_tile((x, y), pos);
+                    assert!(edge.is_on_board(size));
+                    assert!(edge.tiles(size).contains(&Some(((x, y), pos))));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lines() {
+        let size = (2, 5);
+        let count = 3 * 5 + 2 * 6;
+
+        for line in 0..count {
+            let edge = Edge::from_line(line, size);
+            assert!(edge.is_on_board(size));
+            assert_eq!(edge.line(size), line);
+        }
+
+        assert!(!Edge::horizontal(3, 0).is_on_board(size));
+        assert!(!Edge::vertical(0, 6).is_on_board(size));
+    }
+
+    #[test]
+    fn notation() {
+        for edge in [Edge::horizontal(3, 0), Edge::vertical(1, 4)] {
+            assert_eq!(edge.to_string().parse(), Ok(edge));
+        }
+
+        assert_eq!("h 0 1".parse(), Ok(Edge::horizontal(0, 1)));
+        assert_eq!("V,2,0".parse(), Ok(Edge::vertical(2, 0)));
+        assert_eq!("1 1 bottom".parse(), Ok(Edge::horizontal(2, 1)));
+        assert_eq!("0, 2, r".parse(), Ok(Edge::vertical(0, 3)));
+
+        for input in ["", "h 0", "x 0 1", "h -1 0", "1 1 up", "h 0 1 2"] {
+            assert!(input.parse::<Edge>().is_err(), "{}", input);
+        }
+    }
+}
@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+
+use crate::{
+    board::{Game, Player},
+    tile::ChainEnd,
+};
+
+/// The exact final score difference on the perspective of the player to
+/// play, once the game is down to a loony endgame of independent chains and
+/// loops: no safe move left, nothing to take, and every box left in a chain
+/// running from border to border or in a loop. `None` for any other
+/// position, including loony endgames where chains meet at a junction, which
+/// are left to the search.
+///
+/// The player to play has to open one of the chains and loops. The opponent
+/// then either takes all of it and opens the next one, or declines the last
+/// two boxes of a chain of three or more (the last four of a loop) and keeps
+/// control. Chains of one or two boxes are opened so that they cannot be
+/// declined. Every order of opening is tried, on the lengths alone.
+pub fn loony_endgame_value(game: &Game) -> Option<i32> {
+    if game.ended() {
+        return None;
+    }
+
+    let board = game.board();
+    if board.safe_moves_count() > 0 {
+        return None;
+    }
+
+    let (chains, loops) = board.chains_and_loops();
+    let independent = chains
+        .iter()
+        .all(|chain| chain.ends() == [ChainEnd::Border, ChainEnd::Border]);
+    if !independent {
+        return None;
+    }
+
+    let bits = board.bits();
+    let remaining = bits.box_count()
+        - (bits.owned_count(Player::Odd) + bits.owned_count(Player::Even)) as usize;
+    let chained = chains.iter().map(|chain| chain.len()).sum::<usize>()
+        + loops.iter().map(|l| l.len()).sum::<usize>();
+    if chained != remaining {
+        return None;
+    }
+
+    let mut chains: Vec<usize> = chains.iter().map(|chain| chain.len()).collect();
+    let mut loops: Vec<usize> = loops.iter().map(|l| l.len()).collect();
+    chains.sort_unstable();
+    loops.sort_unstable();
+
+    let value = opener_value(&chains, &loops, &mut HashMap::new());
+    Some(game.utility(game.player_to_play()) + value)
+}
+
+/// Net boxes left for the player who has to open one of `chains` or `loops`,
+/// both given as sorted lengths.
+fn opener_value(
+    chains: &[usize],
+    loops: &[usize],
+    memo: &mut HashMap<(Vec<usize>, Vec<usize>), i32>,
+) -> i32 {
+    if chains.is_empty() && loops.is_empty() {
+        return 0;
+    }
+
+    let key = (chains.to_vec(), loops.to_vec());
+    if let Some(&value) = memo.get(&key) {
+        return value;
+    }
+
+    let mut best = i32::MIN;
+
+    for i in 0..chains.len() {
+        if i > 0 && chains[i] == chains[i - 1] {
+            continue;
+        }
+
+        let k = chains[i] as i32;
+        let mut rest = chains.to_vec();
+        rest.remove(i);
+        let v = opener_value(&rest, loops, memo);
+
+        // The opponent takes them all and opens next, or keeps control
+        let reply = if k >= 3 {
+            (k + v).max(k - 4 - v)
+        } else {
+            k + v
+        };
+        best = best.max(-reply);
+    }
+
+    for i in 0..loops.len() {
+        if i > 0 && loops[i] == loops[i - 1] {
+            continue;
+        }
+
+        let k = loops[i] as i32;
+        let mut rest = loops.to_vec();
+        rest.remove(i);
+        let v = opener_value(chains, &rest, memo);
+
+        let reply = (k + v).max(k - 8 - v);
+        best = best.max(-reply);
+    }
+
+    memo.insert(key, best);
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{agent::Agent, board::Game, edge::Edge, rng::Rng};
+
+    use super::loony_endgame_value;
+
+    fn game(rows: usize, cols: usize, edges: &[&str]) -> Game {
+        let mut game = Game::with_size(rows, cols);
+        for edge in edges {
+            game.play(edge.parse::<Edge>().unwrap());
+        }
+        game
+    }
+
+    #[test]
+    fn chains_and_loops() {
+        // A chain of three: the opponent takes it all
+        let strip = game(
+            1,
+            3,
+            &["h 0 0", "h 0 1", "h 0 2", "h 1 0", "h 1 1", "h 1 2"],
+        );
+        assert_eq!(loony_endgame_value(&strip), Some(-3));
+
+        // A loop of four
+        let ring = game(
+            2,
+            2,
+            &[
+                "h 0 0", "h 0 1", "h 2 0", "h 2 1", "v 0 0", "v 1 0", "v 0 2", "v 1 2",
+            ],
+        );
+        assert_eq!(loony_endgame_value(&ring), Some(-4));
+
+        // Safe moves are left, or a box can be taken
+        assert_eq!(loony_endgame_value(&Game::with_size(2, 2)), None);
+        let opened = game(1, 2, &["h 0 0", "h 0 1", "h 1 0", "h 1 1", "v 0 0"]);
+        assert_eq!(loony_endgame_value(&opened), None);
+    }
+
+    #[test]
+    fn matches_search() {
+        let mut rng = Rng::new(23);
+        let mut checked = 0;
+
+        for (rows, cols) in [(2, 3), (3, 3), (3, 3), (3, 4)] {
+            for _ in 0..8 {
+                // Play safe moves at random until there is none left
+                let mut game = Game::with_size(rows, cols);
+                loop {
+                    let safe: Vec<Edge> = game
+                        .available_moves()
+                        .into_iter()
+                        .filter(|&edge| game.board().is_safe(edge))
+                        .collect();
+                    match rng.choose(&safe) {
+                        Some(&edge) => game.play(edge),
+                        None => break,
+                    }
+                }
+
+                let Some(value) = loony_endgame_value(&game) else {
+                    continue;
+                };
+
+                let mut agent = Agent::new(Arc::new(game));
+                agent.set_loony_endgames(false);
+                assert_eq!(agent.ab_search().value, value);
+                checked += 1;
+            }
+        }
+
+        assert!(checked > 0);
+    }
+
+    #[test]
+    fn junctions_fall_back_to_search() {
+        // No safe move and nothing to take, but the chains meet in the
+        // middle of the board
+        let junction = game(
+            3,
+            3,
+            &[
+                "h 0 2", "h 1 0", "h 2 1", "h 1 1", "v 1 3", "h 3 2", "h 0 1", "v 0 3", "h 3 1",
+                "h 3 0", "v 1 0", "v 2 3", "h 0 0", "v 2 0",
+            ],
+        );
+        assert_eq!(junction.board().safe_moves_count(), 0);
+        assert_eq!(loony_endgame_value(&junction), None);
+
+        let junction = Arc::new(junction);
+        let mut plain = Agent::new(junction.clone());
+        plain.set_loony_endgames(false);
+        let mut settled = Agent::new(junction);
+        assert_eq!(settled.ab_search().value, plain.ab_search().value);
+    }
+}
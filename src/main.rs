@@ -1,7 +1,670 @@
-fn main() {
-    println!("Hello, world!");
-}
+use std::{
+    env,
+    io::{self, BufRead, Write},
+    path::PathBuf,
+    process,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use agent::{Agent, Algorithm, SearchLimits};
+use bitboard::{MAX_BOXES, MAX_LINES};
+use board::{Game, Player};
+use mcts::{MctsAgent, MctsLimits, RolloutPolicy};
+use nimstring::NimstringStrategy;
+use rng::Rng;
+use strategy::{GreedyStrategy, HumanStrategy, RandomStrategy, Strategy};
+use tablebase::{Tablebase, TablebaseStrategy};
+use tile::BoardSize;
 
 pub mod agent;
+pub mod bitboard;
 pub mod board;
+pub mod edge;
+pub mod endgame;
+pub mod mcts;
+pub mod nimstring;
+pub mod rng;
+pub mod strategy;
+pub mod strings_and_coins;
+pub mod symmetry;
+pub mod tablebase;
 pub mod tile;
+pub mod transposition;
+pub mod zobrist;
+
+/// Plies searched by the search-bench command without --depth.
+const SEARCH_BENCH_DEPTH: u32 = 8;
+
+const USAGE: &str = "\
+Usage: dots-and-boxes [options]
+       dots-and-boxes tablebase <file> [--size <rows>x<cols>]
+       dots-and-boxes bench [options]
+       dots-and-boxes search-bench [options]
+
+Options:
+  --size <rows>x<cols>   Board size in boxes (default 3x3)
+  --odd <player>         Who plays first: human, agent, mcts, nimstring,
+                         tablebase, greedy or random
+  --even <player>        Who plays second: human, agent, mcts, nimstring,
+                         tablebase, greedy or random
+  --time <ms>            Time the agents may think per move (default 1000)
+  --depth <plies>        Plies the agent may look ahead (default unlimited)
+  --threads <n>          Threads the agent shares its search between
+                         (default 1)
+  --algorithm <name>     How the agent searches: alphabeta (default), pvs
+                         or mtdf
+  --aspiration <width>   Window the agent's deeper iterations start with
+                         around the previous value (default none)
+  --iterations <n>       Simulated games mcts may play per move
+  --exploration <c>      Exploration constant of mcts (default 1.41)
+  --rollout <policy>     How mcts plays out games: random or avoid, which
+                         avoids giving boxes their third side (default)
+  --tablebase <file>     Tablebase for the tablebase player, which solves
+                         the board itself without one
+  --games <n>            Games per board played by bench, positions per
+                         board searched by search-bench (default 2)
+  --help                 Show this message
+
+Without --odd and --even the game asks which mode to play. The tablebase
+command solves every position of the board and writes them to <file>. The
+bench command pits mcts against the alpha-beta agent on every board given
+with --size, 3x3, 4x4 and 5x5 without any, each taking the first move in
+turn. The search-bench command searches random positions with a third of
+their lines drawn on every board given with --size, 3x3 and 4x4 without
+any, with each algorithm in turn to --depth (default 8), and compares them
+with plain alpha-beta. With --threads it runs each of them on every number
+of threads up to the one given and reports the speedup, measured and as
+it would be with a core per thread, from the positions visited on the
+busiest one.
+
+Moves are written `h <row> <col>` for the horizontal line above box row
+<row> (the bottom border is row <rows>), `v <row> <col>` for the vertical
+line left of box column <col> (the right border is column <cols>), or
+`<row> <col> <top|bottom|left|right>` for a side of a box.";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlayerKind {
+    Human,
+    Agent,
+    Mcts,
+    Nimstring,
+    Tablebase,
+    Greedy,
+    Random,
+}
+
+impl PlayerKind {
+    fn parse(name: &str) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "human" => Ok(Self::Human),
+            "agent" => Ok(Self::Agent),
+            "mcts" => Ok(Self::Mcts),
+            "nimstring" => Ok(Self::Nimstring),
+            "tablebase" => Ok(Self::Tablebase),
+            "greedy" => Ok(Self::Greedy),
+            "random" => Ok(Self::Random),
+            _ => Err(format!("unknown player `{}`", name)),
+        }
+    }
+}
+
+struct Options {
+    rows: usize,
+    cols: usize,
+    /// Every size given, in order.
+    sizes: Vec<BoardSize>,
+    odd: Option<PlayerKind>,
+    even: Option<PlayerKind>,
+    limits: SearchLimits,
+    threads: usize,
+    algorithm: Algorithm,
+    aspiration: Option<i32>,
+    iterations: Option<u64>,
+    exploration: f64,
+    rollout: RolloutPolicy,
+    tablebase: Option<PathBuf>,
+    games: usize,
+}
+
+impl Options {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Self {
+            rows: 3,
+            cols: 3,
+            sizes: vec![],
+            odd: None,
+            even: None,
+            limits: SearchLimits {
+                time: Some(Duration::from_millis(1000)),
+                ..SearchLimits::default()
+            },
+            threads: 1,
+            algorithm: Algorithm::default(),
+            aspiration: None,
+            iterations: None,
+            exploration: mcts::DEFAULT_EXPLORATION,
+            rollout: RolloutPolicy::default(),
+            tablebase: None,
+            games: 2,
+        };
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("`{}` needs a value", arg));
+
+            match arg.as_str() {
+                "--size" => {
+                    let value = value()?;
+                    let (rows, cols) = value
+                        .split_once('x')
+                        .and_then(|(rows, cols)| Some((rows.parse().ok()?, cols.parse().ok()?)))
+                        .ok_or(format!("`{}` is not a board size like 3x3", value))?;
+
+                    if rows == 0
+                        || cols == 0
+                        || rows * cols > MAX_BOXES
+                        || bitboard::line_count(rows, cols) > MAX_LINES
+                    {
+                        return Err(format!("a {}x{} board is not supported", rows, cols));
+                    }
+
+                    options.rows = rows;
+                    options.cols = cols;
+                    options.sizes.push((rows, cols));
+                }
+                "--odd" => options.odd = Some(PlayerKind::parse(&value()?)?),
+                "--even" => options.even = Some(PlayerKind::parse(&value()?)?),
+                "--time" => {
+                    let value = value()?;
+                    let millis = value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a number of milliseconds", value))?;
+                    options.limits.time = Some(Duration::from_millis(millis));
+                }
+                "--depth" => {
+                    let value = value()?;
+                    let depth = value
+                        .parse()
+                        .ok()
+                        .filter(|&depth| depth > 0)
+                        .ok_or(format!("`{}` is not a number of plies", value))?;
+                    options.limits.depth = Some(depth);
+                }
+                "--threads" => {
+                    let value = value()?;
+                    options.threads = value
+                        .parse()
+                        .ok()
+                        .filter(|&threads| threads > 0)
+                        .ok_or(format!("`{}` is not a number of threads", value))?;
+                }
+                "--algorithm" => {
+                    options.algorithm = match value()?.to_lowercase().as_str() {
+                        "alphabeta" => Algorithm::AlphaBeta,
+                        "pvs" => Algorithm::Pvs,
+                        "mtdf" => Algorithm::Mtdf,
+                        other => return Err(format!("unknown algorithm `{}`", other)),
+                    }
+                }
+                "--aspiration" => {
+                    let value = value()?;
+                    let width = value
+                        .parse()
+                        .ok()
+                        .filter(|&width| width > 0)
+                        .ok_or(format!("`{}` is not a window width", value))?;
+                    options.aspiration = Some(width);
+                }
+                "--iterations" => {
+                    let value = value()?;
+                    let iterations = value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a number of iterations", value))?;
+                    options.iterations = Some(iterations);
+                }
+                "--exploration" => {
+                    let value = value()?;
+                    options.exploration = value
+                        .parse()
+                        .ok()
+                        .filter(|exploration: &f64| *exploration >= 0.0)
+                        .ok_or(format!("`{}` is not an exploration constant", value))?;
+                }
+                "--rollout" => {
+                    options.rollout = match value()?.as_str() {
+                        "random" => RolloutPolicy::Random,
+                        "avoid" => RolloutPolicy::AvoidThirdSides,
+                        other => return Err(format!("unknown rollout policy `{}`", other)),
+                    }
+                }
+                "--tablebase" => options.tablebase = Some(PathBuf::from(value()?)),
+                "--games" => {
+                    let value = value()?;
+                    options.games = value
+                        .parse()
+                        .map_err(|_| format!("`{}` is not a number of games", value))?;
+                }
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    process::exit(0);
+                }
+                _ => return Err(format!("unknown option `{}`\n\n{}", arg, USAGE)),
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// The alpha-beta agent the options describe, for `game`.
+    fn agent(&self, game: &Game) -> Agent {
+        let mut agent = Agent::new(Arc::new(game.clone()));
+        agent.set_limits(self.limits.clone());
+        agent.set_threads(self.threads);
+        agent.set_algorithm(self.algorithm);
+        agent.set_aspiration_window(self.aspiration);
+        agent
+    }
+
+    /// The MCTS agent the options describe, searching for as long as the
+    /// alpha-beta agent would unless it has a number of iterations.
+    fn mcts(&self) -> MctsAgent {
+        let mut mcts = MctsAgent::default();
+        mcts.set_limits(MctsLimits {
+            iterations: self.iterations,
+            time: self.iterations.map_or(self.limits.time, |_| None),
+        });
+        mcts.set_exploration(self.exploration);
+        mcts.set_policy(self.rollout);
+        mcts
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1).peekable();
+    let result = match args.peek().map(String::as_str) {
+        Some("tablebase") => generate_tablebase(args.skip(1)),
+        Some("bench") => bench(args.skip(1)),
+        Some("search-bench") => search_bench(args.skip(1)),
+        _ => run(args),
+    };
+
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        process::exit(1);
+    }
+}
+
+fn run(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+
+    let (odd, even) = match (options.odd, options.even) {
+        (Some(odd), Some(even)) => (odd, even),
+        (odd, even) => {
+            let (odd_choice, even_choice) = choose_mode(&mut input)?;
+            (odd.unwrap_or(odd_choice), even.unwrap_or(even_choice))
+        }
+    };
+
+    let mut game = Game::with_size(options.rows, options.cols);
+    let mut odd = player(odd, &game, &options)?;
+    let mut even = player(even, &game, &options)?;
+    // Both humans read from the same terminal
+    let mut human = HumanStrategy::new(input, io::stdout());
+
+    game.print_board();
+
+    while !game.ended() {
+        let mover = game.player_to_play();
+        let strategy = match mover {
+            Player::Odd => odd.as_deref_mut(),
+            Player::Even => even.as_deref_mut(),
+        };
+
+        let edge = match strategy {
+            Some(strategy) => strategy.choose_move(&game),
+            None => human
+                .try_choose_move(&game)
+                .map_err(|err| err.to_string())?,
+        };
+        let outcome = game.try_play(edge).map_err(|err| err.to_string())?;
+
+        println!("{:?} plays {}", mover, edge);
+        if outcome.extra_turn() {
+            println!(
+                "{:?} completes {} box(es) and plays again",
+                mover,
+                outcome.completed().len()
+            );
+        }
+        game.print_board();
+    }
+
+    let odd = game.board().bits().owned_count(Player::Odd);
+    let even = game.board().bits().owned_count(Player::Even);
+    println!("Final score: Odd {} - Even {}", odd, even);
+    match odd.cmp(&even) {
+        std::cmp::Ordering::Greater => println!("Odd wins"),
+        std::cmp::Ordering::Less => println!("Even wins"),
+        std::cmp::Ordering::Equal => println!("Draw"),
+    }
+
+    Ok(())
+}
+
+/// Ask which of the usual pairings to play until a valid answer is given.
+fn choose_mode(input: &mut impl BufRead) -> Result<(PlayerKind, PlayerKind), String> {
+    loop {
+        println!("1) human vs human");
+        println!("2) human vs agent");
+        println!("3) agent vs human");
+        println!("4) agent vs agent");
+        print!("Choose a mode: ");
+        io::stdout().flush().map_err(|err| err.to_string())?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line).map_err(|err| err.to_string())? == 0 {
+            return Err("input ended before a mode was chosen".to_string());
+        }
+
+        match line.trim() {
+            "1" => return Ok((PlayerKind::Human, PlayerKind::Human)),
+            "2" => return Ok((PlayerKind::Human, PlayerKind::Agent)),
+            "3" => return Ok((PlayerKind::Agent, PlayerKind::Human)),
+            "4" => return Ok((PlayerKind::Agent, PlayerKind::Agent)),
+            other => println!("`{}` is not one of the modes", other),
+        }
+    }
+}
+
+/// Solve the board given by the options and write its tablebase to the
+/// file named first.
+fn generate_tablebase(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let path = args
+        .next()
+        .filter(|path| !path.starts_with("--"))
+        .ok_or("the tablebase command needs a file to write")?;
+    let options = Options::parse(args)?;
+    let size = (options.rows, options.cols);
+
+    if bitboard::line_count(size.0, size.1) > tablebase::MAX_LINES {
+        return Err(format!(
+            "a {}x{} board has too many lines to tabulate",
+            size.0, size.1
+        ));
+    }
+
+    let start = Instant::now();
+    let tablebase = Tablebase::generate(size);
+    tablebase.save(&path).map_err(|err| err.to_string())?;
+
+    let value = tablebase.game_value(&Game::with_size(size.0, size.1));
+    let outcome = match value.cmp(&0) {
+        std::cmp::Ordering::Greater => format!("Odd wins by {}", value),
+        std::cmp::Ordering::Less => format!("Even wins by {}", -value),
+        std::cmp::Ordering::Equal => "it is a draw".to_string(),
+    };
+    println!(
+        "Solved {}x{} in {:.1?}: with perfect play {}",
+        size.0,
+        size.1,
+        start.elapsed(),
+        outcome
+    );
+
+    Ok(())
+}
+
+/// Play MCTS against alpha-beta on each board and report how MCTS fared.
+fn bench(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let sizes = if options.sizes.is_empty() {
+        vec![(3, 3), (4, 4), (5, 5)]
+    } else {
+        options.sizes.clone()
+    };
+
+    for (rows, cols) in sizes {
+        let (mut wins, mut draws, mut losses) = (0, 0, 0);
+        let mut margin = 0;
+        let (mut iterations, mut moves) = (0, 0);
+        let start = Instant::now();
+
+        for round in 0..options.games {
+            let mcts_turn = if round % 2 == 0 {
+                Player::Odd
+            } else {
+                Player::Even
+            };
+            let mut game = Game::with_size(rows, cols);
+            let mut mcts = options.mcts();
+            let mut agent = options.agent(&game);
+
+            while !game.ended() {
+                let edge = if game.player_to_play() == mcts_turn {
+                    let edge = mcts.choose_move(&game);
+                    iterations += mcts.iterations();
+                    moves += 1;
+                    edge
+                } else {
+                    agent.choose_move(&game)
+                };
+
+                game.play(edge);
+            }
+
+            let utility = game.utility(mcts_turn);
+            margin += utility;
+            match utility.cmp(&0) {
+                std::cmp::Ordering::Greater => wins += 1,
+                std::cmp::Ordering::Equal => draws += 1,
+                std::cmp::Ordering::Less => losses += 1,
+            }
+        }
+
+        let games = options.games.max(1) as f64;
+        println!(
+            "{}x{}: mcts won {}, drew {} and lost {} against alpha-beta, \
+             mean margin {:+.1}, {} iterations per move, {:.1?}",
+            rows,
+            cols,
+            wins,
+            draws,
+            losses,
+            margin as f64 / games,
+            iterations / moves.max(1),
+            start.elapsed()
+        );
+    }
+
+    Ok(())
+}
+
+/// Search the same random positions of each board with every algorithm, on
+/// one thread up to as many as given, and report the nodes and time each
+/// took against plain alpha-beta on one thread, and the speedup over the
+/// same algorithm on one thread.
+fn search_bench(args: impl Iterator<Item = String>) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let sizes = if options.sizes.is_empty() {
+        vec![(3, 3), (4, 4)]
+    } else {
+        options.sizes.clone()
+    };
+    let algorithms = [
+        ("alpha-beta", Algorithm::AlphaBeta, None),
+        ("pvs", Algorithm::Pvs, None),
+        ("mtdf", Algorithm::Mtdf, None),
+        ("alpha-beta, aspiration 2", Algorithm::AlphaBeta, Some(2)),
+        ("pvs, aspiration 2", Algorithm::Pvs, Some(2)),
+    ];
+    // Node counts only compare at a fixed depth
+    let limits = SearchLimits {
+        depth: Some(options.limits.depth.unwrap_or(SEARCH_BENCH_DEPTH)),
+        ..SearchLimits::default()
+    };
+
+    for (rows, cols) in sizes {
+        let mut rng = Rng::new(25);
+        let positions: Vec<Game> = (0..options.games)
+            .map(|_| {
+                let mut game = Game::with_size(rows, cols);
+                // Early enough for the searches to take a while
+                for _ in 0..bitboard::line_count(rows, cols) / 3 {
+                    let edge = *rng.choose(&game.available_moves()).unwrap();
+                    game.play(edge);
+                }
+                game
+            })
+            .collect();
+
+        println!("{}x{}, {} positions:", rows, cols, positions.len());
+        let mut baseline = None;
+        for (name, algorithm, aspiration) in algorithms {
+            let mut serial = None;
+
+            for threads in 1..=options.threads {
+                let (mut nodes, mut critical, mut values) = (0, 0, vec![]);
+                let start = Instant::now();
+
+                for game in positions.iter() {
+                    let mut agent = Agent::new(Arc::new(game.clone()));
+                    agent.set_limits(limits.clone());
+                    agent.set_threads(threads);
+                    agent.set_algorithm(algorithm);
+                    agent.set_aspiration_window(aspiration);
+
+                    let result = agent.ab_search();
+                    nodes += result.nodes;
+                    critical += agent.critical_nodes();
+                    values.push(result.value);
+                }
+
+                let elapsed = start.elapsed();
+                let (base_nodes, base_values) = baseline.get_or_insert((nodes, values.clone()));
+                let (serial, serial_nodes) = *serial.get_or_insert((elapsed, nodes));
+                println!(
+                    "  {:<26}{:>3} thread(s){:>12} nodes ({:>5.1}%) in {:.1?}, {:.2}x \
+                     ({:.2}x with a core per thread){}",
+                    name,
+                    threads,
+                    nodes,
+                    100.0 * nodes as f64 / (*base_nodes).max(1) as f64,
+                    elapsed,
+                    serial.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON),
+                    serial_nodes as f64 / critical.max(1) as f64,
+                    if values == *base_values {
+                        ""
+                    } else {
+                        ", values differ"
+                    }
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// The strategy playing `kind`, or `None` for a human.
+fn player(
+    kind: PlayerKind,
+    game: &Game,
+    options: &Options,
+) -> Result<Option<Box<dyn Strategy>>, String> {
+    let strategy: Box<dyn Strategy> = match kind {
+        PlayerKind::Human => return Ok(None),
+        PlayerKind::Agent => Box::new(options.agent(game)),
+        PlayerKind::Mcts => Box::new(options.mcts()),
+        PlayerKind::Nimstring => Box::new(NimstringStrategy::new()),
+        PlayerKind::Tablebase => {
+            let size = game.size();
+            let tablebase = match &options.tablebase {
+                Some(path) => Tablebase::load(path).map_err(|err| {
+                    format!("cannot read tablebase `{}`: {}", path.display(), err)
+                })?,
+                None if bitboard::line_count(size.0, size.1) <= tablebase::MAX_LINES => {
+                    Tablebase::generate(size)
+                }
+                None => {
+                    return Err(format!(
+                        "a {}x{} board has too many lines to tabulate",
+                        size.0, size.1
+                    ))
+                }
+            };
+
+            if tablebase.size() != size {
+                return Err(format!(
+                    "the tablebase is for {}x{} boards",
+                    tablebase.size().0,
+                    tablebase.size().1
+                ));
+            }
+
+            Box::new(TablebaseStrategy::new(tablebase))
+        }
+        PlayerKind::Greedy => Box::new(GreedyStrategy),
+        PlayerKind::Random => Box::new(RandomStrategy::default()),
+    };
+
+    Ok(Some(strategy))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{agent::Algorithm, mcts::RolloutPolicy};
+
+    use super::{Options, PlayerKind};
+
+    fn parse(args: &str) -> Result<Options, String> {
+        Options::parse(args.split_whitespace().map(String::from))
+    }
+
+    #[test]
+    fn options() {
+        let options =
+            parse("--size 2x4 --odd Agent --even human --depth 6 --threads 4 --tablebase 2x4.dbtb")
+                .unwrap();
+        assert_eq!((options.rows, options.cols), (2, 4));
+        assert_eq!(options.odd, Some(PlayerKind::Agent));
+        assert_eq!(options.even, Some(PlayerKind::Human));
+        assert_eq!(options.limits.depth, Some(6));
+        assert_eq!(options.threads, 4);
+        assert_eq!(options.tablebase, Some("2x4.dbtb".into()));
+
+        let options =
+            parse("--size 3x3 --size 4x4 --iterations 500 --rollout random --games 4").unwrap();
+        assert_eq!(options.sizes, vec![(3, 3), (4, 4)]);
+        assert_eq!((options.rows, options.cols), (4, 4));
+        assert_eq!(options.iterations, Some(500));
+        assert_eq!(options.rollout, RolloutPolicy::Random);
+        assert_eq!(options.games, 4);
+
+        let options = parse("--algorithm MTDF --aspiration 2").unwrap();
+        assert_eq!(options.algorithm, Algorithm::Mtdf);
+        assert_eq!(options.aspiration, Some(2));
+
+        let options = parse("").unwrap();
+        assert_eq!((options.rows, options.cols), (3, 3));
+        assert_eq!(options.odd, None);
+
+        for args in [
+            "--size 3",
+            "--size 0x3",
+            "--size 8x8",
+            "--odd robot",
+            "--time",
+            "--depth 0",
+            "--threads 0",
+            "--exploration -1",
+            "--rollout smart",
+            "--algorithm negascout",
+            "--aspiration 0",
+            "--fast",
+        ] {
+            assert!(parse(args).is_err(), "{}", args);
+        }
+    }
+}
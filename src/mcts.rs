@@ -0,0 +1,428 @@
+use std::time::{Duration, Instant};
+
+use crate::{
+    board::{Game, Player},
+    edge::Edge,
+    rng::Rng,
+    strategy::{Move, Strategy},
+};
+
+/// Iterations run by a search without any limit.
+pub const DEFAULT_ITERATIONS: u64 = 10_000;
+
+/// Exploration constant of the UCT formula, the theoretical √2 for rewards
+/// between zero and one.
+pub const DEFAULT_EXPLORATION: f64 = std::f64::consts::SQRT_2;
+
+/// How the moves of a simulated game are picked once it leaves the tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RolloutPolicy {
+    /// Any legal move, uniformly.
+    Random,
+    /// A random box-completing move if there is one, otherwise a random move
+    /// giving no box its third side, and only then any move.
+    #[default]
+    AvoidThirdSides,
+}
+
+/// Budget for [`MctsAgent::search`]. The search stops at whichever limit it
+/// reaches first and runs [`DEFAULT_ITERATIONS`] without any.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MctsLimits {
+    /// Number of simulated games.
+    pub iterations: Option<u64>,
+    /// Wall-clock time the search may take.
+    pub time: Option<Duration>,
+}
+
+/// A position reached in the search tree.
+struct Node {
+    /// The move leading here from the parent, `None` at the root.
+    edge: Option<Edge>,
+    /// The player who played `edge`.
+    mover: Player,
+    children: Vec<usize>,
+    /// Legal moves without a child yet.
+    untried: Vec<Edge>,
+    visits: u64,
+    /// Games won by `mover` out of `visits`, draws counting half.
+    wins: f64,
+}
+
+impl Node {
+    fn new(edge: Option<Edge>, mover: Player, game: &Game) -> Self {
+        let untried = if game.ended() {
+            vec![]
+        } else {
+            game.available_moves()
+        };
+
+        Self {
+            edge,
+            mover,
+            children: vec![],
+            untried,
+            visits: 0,
+            wins: 0.0,
+        }
+    }
+}
+
+/// Monte Carlo tree search with the UCT selection rule.
+///
+/// Every iteration walks down the tree picking the child with the best upper
+/// confidence bound, adds one new position, plays the game out with the
+/// rollout policy and credits the result to every position on the way. The
+/// most visited move at the root is played. The part of the tree below the
+/// moves played since the last search is kept for the next one.
+pub struct MctsAgent {
+    limits: MctsLimits,
+    exploration: f64,
+    policy: RolloutPolicy,
+    rng: Rng,
+    /// The nodes of the tree, the root first.
+    tree: Vec<Node>,
+    /// The position at the root of `tree`.
+    root: Option<Game>,
+    iterations: u64,
+}
+
+impl MctsAgent {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            limits: MctsLimits::default(),
+            exploration: DEFAULT_EXPLORATION,
+            policy: RolloutPolicy::default(),
+            rng: Rng::new(seed),
+            tree: vec![],
+            root: None,
+            iterations: 0,
+        }
+    }
+
+    pub fn set_limits(&mut self, limits: MctsLimits) {
+        self.limits = limits;
+    }
+
+    pub fn set_exploration(&mut self, exploration: f64) {
+        self.exploration = exploration;
+    }
+
+    pub fn set_policy(&mut self, policy: RolloutPolicy) {
+        self.policy = policy;
+    }
+
+    /// Number of iterations run by the last search.
+    pub fn iterations(&self) -> u64 {
+        self.iterations
+    }
+
+    /// Number of positions in the tree.
+    pub fn tree_size(&self) -> usize {
+        self.tree.len()
+    }
+
+    /// Number of simulated games through the root, including those kept
+    /// from earlier searches.
+    pub fn root_visits(&self) -> u64 {
+        self.tree.first().map_or(0, |root| root.visits)
+    }
+
+    /// Search `game` within the limits and return the most visited move,
+    /// `None` if the game is over.
+    pub fn search(&mut self, game: &Game) -> Option<Edge> {
+        let start = Instant::now();
+        let deadline = self.limits.time.map(|time| start + time);
+        let iterations = match self.limits {
+            MctsLimits {
+                iterations: None,
+                time: None,
+            } => Some(DEFAULT_ITERATIONS),
+            limits => limits.iterations,
+        };
+
+        self.reuse(game);
+        if self.tree.is_empty() {
+            let mover = game.player_to_play().opponent();
+            self.tree.push(Node::new(None, mover, game));
+        }
+
+        self.iterations = 0;
+        while iterations.is_none_or(|iterations| self.iterations < iterations)
+            && deadline.is_none_or(|deadline| Instant::now() < deadline)
+        {
+            self.iterate(game);
+            self.iterations += 1;
+        }
+
+        // The most visited move is the most reliable, its value being
+        // averaged over the most games
+        let root = &self.tree[0];
+        let best = root
+            .children
+            .iter()
+            .max_by_key(|&&child| self.tree[child].visits)
+            .and_then(|&child| self.tree[child].edge);
+
+        best.or_else(|| root.untried.first().copied())
+    }
+
+    /// One simulated game from `game`, the position at the root.
+    fn iterate(&mut self, game: &Game) {
+        let mut game = game.clone();
+        let mut path = vec![0];
+        let mut node = 0;
+
+        while self.tree[node].untried.is_empty() && !self.tree[node].children.is_empty() {
+            node = self.select(node);
+            game.play(self.tree[node].edge.unwrap());
+            path.push(node);
+        }
+
+        if !self.tree[node].untried.is_empty() {
+            let untried = &mut self.tree[node].untried;
+            let edge = untried.swap_remove(self.rng.below(untried.len()));
+            let mover = game.player_to_play();
+            game.play(edge);
+
+            let child = self.tree.len();
+            self.tree.push(Node::new(Some(edge), mover, &game));
+            self.tree[node].children.push(child);
+            path.push(child);
+        }
+
+        self.rollout(&mut game);
+        let margin = game.utility(Player::Odd);
+
+        for &node in path.iter() {
+            let node = &mut self.tree[node];
+            let margin = match node.mover {
+                Player::Odd => margin,
+                Player::Even => -margin,
+            };
+
+            node.visits += 1;
+            node.wins += match margin.signum() {
+                1 => 1.0,
+                0 => 0.5,
+                _ => 0.0,
+            };
+        }
+    }
+
+    /// The child of `node` with the highest upper confidence bound.
+    fn select(&self, node: usize) -> usize {
+        let log_visits = (self.tree[node].visits as f64).ln();
+        let bound = |child: usize| {
+            let child = &self.tree[child];
+            let visits = child.visits as f64;
+            child.wins / visits + self.exploration * (log_visits / visits).sqrt()
+        };
+
+        *self.tree[node]
+            .children
+            .iter()
+            .max_by(|&&a, &&b| bound(a).total_cmp(&bound(b)))
+            .unwrap()
+    }
+
+    /// Play `game` to the end with the rollout policy.
+    fn rollout(&mut self, game: &mut Game) {
+        while !game.ended() {
+            let moves = game.available_moves();
+            let board = game.board();
+
+            let candidates: Vec<Edge> = match self.policy {
+                RolloutPolicy::Random => moves,
+                RolloutPolicy::AvoidThirdSides => {
+                    let captures: Vec<Edge> = moves
+                        .iter()
+                        .copied()
+                        .filter(|&edge| board.completes_box(edge))
+                        .collect();
+                    let safe: Vec<Edge> = moves
+                        .iter()
+                        .copied()
+                        .filter(|&edge| board.is_safe(edge))
+                        .collect();
+
+                    [captures, safe, moves]
+                        .into_iter()
+                        .find(|moves| !moves.is_empty())
+                        .unwrap()
+                }
+            };
+
+            let edge = *self.rng.choose(&candidates).unwrap();
+            game.play(edge);
+        }
+    }
+
+    /// Keep the subtree of the position reached from the previous root by
+    /// the moves played since, if `game` is such a position.
+    fn reuse(&mut self, game: &Game) {
+        let subtree = self.root.as_ref().and_then(|root| {
+            let before = root.history();
+            let played = game.history();
+
+            if root.size() != game.size()
+                || played.len() < before.len()
+                || before
+                    .iter()
+                    .zip(played.iter())
+                    .any(|(a, b)| a.edge() != b.edge())
+            {
+                return None;
+            }
+
+            let mut node = 0;
+            for outcome in played[before.len()..].iter() {
+                node = *self.tree[node]
+                    .children
+                    .iter()
+                    .find(|&&child| self.tree[child].edge == Some(outcome.edge()))?;
+            }
+
+            Some(node)
+        });
+
+        match subtree {
+            Some(node) if !self.tree.is_empty() => self.extract(node),
+            _ => self.tree.clear(),
+        }
+
+        self.root = Some(game.clone());
+    }
+
+    /// Make `node` the root, dropping every node outside its subtree.
+    fn extract(&mut self, node: usize) {
+        let mut old = std::mem::take(&mut self.tree);
+        let mut stack: Vec<(usize, Option<usize>)> = vec![(node, None)];
+
+        while let Some((id, parent)) = stack.pop() {
+            let mut moved = Node {
+                children: vec![],
+                untried: std::mem::take(&mut old[id].untried),
+                ..old[id]
+            };
+            if parent.is_none() {
+                moved.edge = None;
+            }
+
+            let index = self.tree.len();
+            self.tree.push(moved);
+            if let Some(parent) = parent {
+                self.tree[parent].children.push(index);
+            }
+
+            for &child in old[id].children.iter().rev() {
+                stack.push((child, Some(index)));
+            }
+        }
+    }
+}
+
+impl Default for MctsAgent {
+    fn default() -> Self {
+        Self::new(Rng::from_time().next_u64())
+    }
+}
+
+impl Strategy for MctsAgent {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        self.search(game).expect("no move left to choose")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crate::{
+        board::Game,
+        edge::Edge,
+        strategy::{play_out, RandomStrategy, Strategy},
+    };
+
+    use super::{MctsAgent, MctsLimits, RolloutPolicy};
+
+    fn agent(iterations: u64, policy: RolloutPolicy) -> MctsAgent {
+        let mut agent = MctsAgent::new(19);
+        agent.set_limits(MctsLimits {
+            iterations: Some(iterations),
+            time: None,
+        });
+        agent.set_policy(policy);
+        agent
+    }
+
+    #[test]
+    fn takes_the_last_boxes() {
+        // Two boxes hang on the middle line of a 1×2 board
+        let mut game = Game::with_size(1, 2);
+        for edge in ["h 0 0", "h 0 1", "h 1 0", "h 1 1", "v 0 0", "v 0 2"] {
+            game.play(edge.parse::<Edge>().unwrap());
+        }
+
+        for policy in [RolloutPolicy::Random, RolloutPolicy::AvoidThirdSides] {
+            let mut mcts = agent(100, policy);
+            assert_eq!(mcts.search(&game), Some(Edge::vertical(0, 1)));
+        }
+
+        game.play(Edge::vertical(0, 1));
+        assert_eq!(agent(100, RolloutPolicy::Random).search(&game), None);
+    }
+
+    #[test]
+    fn beats_random() {
+        let mut mcts = agent(200, RolloutPolicy::AvoidThirdSides);
+        let mut random = RandomStrategy::new(19);
+
+        let mut wins = 0;
+        for _ in 0..4 {
+            let mut game = Game::with_size(3, 3);
+            if play_out(&mut game, &mut mcts, &mut random) > 0 {
+                wins += 1;
+            }
+        }
+
+        assert!(wins >= 3, "mcts won {} of 4 games", wins);
+    }
+
+    #[test]
+    fn tree_reuse() {
+        let mut mcts = agent(300, RolloutPolicy::AvoidThirdSides);
+        let mut game = Game::with_size(2, 2);
+
+        let edge = mcts.choose_move(&game);
+        assert_eq!(mcts.root_visits(), 300);
+        game.play(edge);
+        let reply = game.available_moves()[0];
+        game.play(reply);
+
+        // The visits below the moves played are kept
+        mcts.choose_move(&game);
+        assert_eq!(mcts.iterations(), 300);
+        assert!(mcts.root_visits() > 300);
+        assert!(mcts.tree_size() as u64 <= mcts.root_visits() + 1);
+
+        // A different game starts over
+        let game = Game::with_size(2, 2);
+        mcts.choose_move(&game);
+        assert_eq!(mcts.root_visits(), 300);
+    }
+
+    #[test]
+    fn time_budget() {
+        let mut mcts = MctsAgent::new(19);
+        mcts.set_limits(MctsLimits {
+            iterations: None,
+            time: Some(Duration::from_millis(50)),
+        });
+
+        let start = Instant::now();
+        mcts.choose_move(&Game::with_size(3, 3));
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert!(mcts.iterations() > 0);
+    }
+}
@@ -0,0 +1,539 @@
+use std::collections::HashMap;
+
+use crate::{
+    board::{Board, Game},
+    edge::Edge,
+    strategy::{GreedyStrategy, Move, Strategy},
+    strings_and_coins::{Node, StringsAndCoins},
+    tile::BoardSize,
+};
+
+/// Positions with more undrawn lines than this are left to the fallback
+/// strategy by [`NimstringStrategy`], their nimstring values take too long to
+/// compute.
+pub const DEFAULT_MAX_LINES: usize = 20;
+
+/// Value of a Nimstring position for the player to move.
+///
+/// Nimstring is dots and boxes seen as strings and coins, where the player
+/// who cannot move wins nothing: completing a coin forces another move and
+/// whoever has no move left loses. Winning Nimstring is winning the fight for
+/// control.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NimValue {
+    /// The position is worth the nim heap of this size. Zero is a loss for
+    /// the player to move.
+    Nimber(u32),
+    /// The last move offered a long chain, a loop or a half-hearted handout:
+    /// the player to move wins by either taking everything or declining the
+    /// last boxes, whatever else is on the board.
+    Loony,
+}
+
+impl NimValue {
+    /// Whether the player to move wins.
+    pub fn is_win(&self) -> bool {
+        *self != Self::Nimber(0)
+    }
+}
+
+/// Nimstring value calculator for one strings-and-coins graph, usually the
+/// graph of an empty board of some size. Positions are sets of strings left
+/// uncut; the values of independent components are remembered between
+/// calls.
+pub struct Nimstring {
+    /// Strings tied to each coin.
+    coin_strings: Vec<u128>,
+    /// The ends of each string.
+    string_ends: Vec<[Node; 2]>,
+    memo: HashMap<u128, u32>,
+}
+
+impl Nimstring {
+    /// Calculator for the positions of boards of `size`.
+    pub fn new(size: BoardSize) -> Self {
+        Self::with_graph(&StringsAndCoins::from_board(&Board::with_size(
+            size.0, size.1,
+        )))
+    }
+
+    /// Calculator for the positions of `graph`, whatever strings are left
+    /// in it. Panics if it has more than 128 strings.
+    pub fn with_graph(graph: &StringsAndCoins) -> Self {
+        if graph.string_count() > u128::BITS as usize {
+            panic!("{} strings do not fit in a mask", graph.string_count())
+        }
+
+        let mut coin_strings = vec![0; graph.coin_count()];
+        let string_ends: Vec<[Node; 2]> = (0..graph.string_count())
+            .map(|string| graph.ends(string))
+            .collect();
+
+        for (string, ends) in string_ends.iter().enumerate() {
+            for &end in ends {
+                if let Node::Coin(coin) = end {
+                    coin_strings[coin] |= 1 << string;
+                }
+            }
+        }
+
+        Self {
+            coin_strings,
+            string_ends,
+            memo: HashMap::new(),
+        }
+    }
+
+    /// Number of component values remembered so far.
+    pub fn memo_len(&self) -> usize {
+        self.memo.len()
+    }
+
+    /// Value of `board` for the player to move.
+    pub fn value(&mut self, board: &Board) -> NimValue {
+        self.graph_value(&StringsAndCoins::from_board(board))
+    }
+
+    /// Value of `graph` for the player to move. The graph must have the
+    /// strings of the one this calculator was made for, cut or not.
+    pub fn graph_value(&mut self, graph: &StringsAndCoins) -> NimValue {
+        self.value_of(graph.string_mask())
+    }
+
+    /// Value of the position whose undrawn lines are `undrawn`, for the
+    /// player to move.
+    pub fn value_of(&mut self, undrawn: u128) -> NimValue {
+        let undrawn = match self.normalize(undrawn) {
+            Some(undrawn) => undrawn,
+            None => return NimValue::Loony,
+        };
+
+        let mut sum = 0;
+        for component in self.components(undrawn) {
+            sum ^= self.component_value(component);
+        }
+
+        NimValue::Nimber(sum)
+    }
+
+    /// Split `undrawn` into independent components: coins joined by undrawn
+    /// lines, together with their lines to the ground. Lines to the ground
+    /// do not join anything.
+    pub fn components(&self, undrawn: u128) -> Vec<u128> {
+        let mut components = vec![];
+        let mut left = undrawn;
+
+        while left != 0 {
+            let mut component = 0;
+            let mut frontier = left & left.wrapping_neg();
+
+            while frontier != 0 {
+                component |= frontier;
+
+                let mut next = 0;
+                for line in lines(frontier) {
+                    for coin in self.coins(line) {
+                        next |= self.coin_strings[coin] & undrawn;
+                    }
+                }
+
+                frontier = next & !component;
+            }
+
+            components.push(component);
+            left &= !component;
+        }
+
+        components
+    }
+
+    /// Take every coin that can be taken without giving anything up, since
+    /// taking it and moving on is as good as the move on its own. Returns
+    /// `None` if the position is loony instead.
+    fn normalize(&self, mut undrawn: u128) -> Option<u128> {
+        'capture: loop {
+            for (coin, &lines) in self.coin_strings.iter().enumerate() {
+                let strings = lines & undrawn;
+                if strings.count_ones() != 1 {
+                    continue;
+                }
+
+                let string = strings.trailing_zeros() as usize;
+                if let Some(next) = self.other_coin(string, coin) {
+                    let next_strings = self.coin_strings[next] & undrawn;
+
+                    // Taking the coin leaves the next one capturable: the
+                    // player may take both or hand them over with the far
+                    // string of the next coin, unless that one leads to yet
+                    // another capturable coin
+                    if next_strings.count_ones() == 2 {
+                        let far = (next_strings & !strings).trailing_zeros() as usize;
+                        let beyond = self.other_coin(far, next);
+
+                        if beyond.is_none_or(|b| (self.coin_strings[b] & undrawn).count_ones() != 1)
+                        {
+                            return None;
+                        }
+                    }
+                }
+
+                undrawn &= !strings;
+                continue 'capture;
+            }
+
+            return Some(undrawn);
+        }
+    }
+
+    fn component_value(&mut self, component: u128) -> u32 {
+        if let Some(&value) = self.memo.get(&component) {
+            return value;
+        }
+
+        // Moves into loony positions lose and are left out
+        let mut options = vec![];
+        for line in lines(component) {
+            if let NimValue::Nimber(value) = self.value_of(component & !(1 << line)) {
+                options.push(value);
+            }
+        }
+
+        let value = mex(&options);
+        self.memo.insert(component, value);
+
+        value
+    }
+
+    /// The coin on the other side of `line` from `coin`, `None` for the
+    /// ground.
+    fn other_coin(&self, line: usize, coin: usize) -> Option<usize> {
+        self.coins(line).find(|&other| other != coin)
+    }
+
+    /// The coins tied by `line`.
+    fn coins(&self, line: usize) -> impl Iterator<Item = usize> {
+        self.string_ends[line]
+            .into_iter()
+            .filter_map(|end| match end {
+                Node::Coin(coin) => Some(coin),
+                Node::Ground => None,
+            })
+    }
+}
+
+/// Plays for control: during the safe-move phase it picks a move leaving a
+/// position of nim-sum zero, so that the opponent is the one who runs out of
+/// safe moves. Takes every box it can unless nimstring says otherwise, and
+/// falls back to [`GreedyStrategy`] when the position is lost or too large to
+/// analyse.
+pub struct NimstringStrategy {
+    nimstring: Option<(BoardSize, Nimstring)>,
+    max_lines: usize,
+    fallback: GreedyStrategy,
+}
+
+impl NimstringStrategy {
+    pub fn new() -> Self {
+        Self {
+            nimstring: None,
+            max_lines: DEFAULT_MAX_LINES,
+            fallback: GreedyStrategy,
+        }
+    }
+
+    /// Analyse positions with at most `max_lines` undrawn lines.
+    pub fn set_max_lines(&mut self, max_lines: usize) {
+        self.max_lines = max_lines;
+    }
+
+    /// A move that wins Nimstring from `game`, if there is one.
+    fn winning_move(&mut self, game: &Game) -> Option<Move> {
+        let size = game.size();
+        let nimstring = match &mut self.nimstring {
+            Some((analysed, nimstring)) if *analysed == size => nimstring,
+            other => &mut other.insert((size, Nimstring::new(size))).1,
+        };
+
+        let board = game.board();
+        let undrawn = StringsAndCoins::from_board(board).string_mask();
+        let moves = game.available_moves();
+
+        // Boxes first: taking one keeps the move, so the position left must
+        // be a win for the mover. Any other move hands it over, and safe
+        // ones are tried before those giving boxes away.
+        let (captures, mut others): (Vec<Edge>, Vec<Edge>) =
+            moves.iter().partition(|&&edge| board.completes_box(edge));
+        others.sort_by_key(|&edge| !board.is_safe(edge));
+
+        let captured = captures.into_iter().find(|&edge| {
+            let line = edge.line(size);
+            nimstring.value_of(undrawn & !(1 << line)).is_win()
+        });
+
+        captured.or_else(|| {
+            others.into_iter().find(|&edge| {
+                let line = edge.line(size);
+                !nimstring.value_of(undrawn & !(1 << line)).is_win()
+            })
+        })
+    }
+}
+
+impl Default for NimstringStrategy {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Strategy for NimstringStrategy {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        let undrawn = game.available_moves().len();
+
+        let choice = if undrawn <= self.max_lines {
+            self.winning_move(game)
+        } else {
+            None
+        };
+
+        choice.unwrap_or_else(|| self.fallback.choose_move(game))
+    }
+}
+
+/// Indices of the set bits of `mask`.
+fn lines(mut mask: u128) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if mask == 0 {
+            return None;
+        }
+
+        let line = mask.trailing_zeros() as usize;
+        mask &= mask - 1;
+        Some(line)
+    })
+}
+
+/// Smallest value missing from `values`.
+fn mex(values: &[u32]) -> u32 {
+    (0..).find(|value| !values.contains(value)).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::{
+        bitboard::Bitboard,
+        board::{Board, Game, Player},
+        edge::Edge,
+        strategy::Strategy,
+        strings_and_coins::{Node, StringsAndCoins},
+    };
+
+    use super::{NimValue, Nimstring, NimstringStrategy};
+
+    fn board_with(rows: usize, cols: usize, edges: &[Edge]) -> Board {
+        let mut board = Board::with_size(rows, cols);
+        for &edge in edges {
+            board.mark_for(edge, Player::Odd);
+        }
+        board
+    }
+
+    /// A row of `len` boxes with their tops and bottoms drawn, grounded at
+    /// both ends.
+    fn chain(len: usize) -> Board {
+        let edges: Vec<Edge> = (0..len)
+            .flat_map(|col| [Edge::horizontal(0, col), Edge::horizontal(1, col)])
+            .collect();
+        board_with(1, len, &edges)
+    }
+
+    #[test]
+    fn known_values() {
+        // A lone coin, and two coins in a row, are worth *1: the only good
+        // move is to cut the string in the middle
+        let mut nimstring = Nimstring::new((1, 1));
+        assert_eq!(nimstring.value(&chain(1)), NimValue::Nimber(1));
+
+        let mut nimstring = Nimstring::new((1, 2));
+        assert_eq!(nimstring.value(&chain(2)), NimValue::Nimber(1));
+
+        // Long chains are worth nothing: every move in them is loony
+        for len in 3..6 {
+            let mut nimstring = Nimstring::new((1, len));
+            assert_eq!(nimstring.value(&chain(len)), NimValue::Nimber(0));
+        }
+
+        // A loop of four
+        let loop_edges = [
+            Edge::horizontal(0, 0),
+            Edge::horizontal(0, 1),
+            Edge::horizontal(2, 0),
+            Edge::horizontal(2, 1),
+            Edge::vertical(0, 0),
+            Edge::vertical(1, 0),
+            Edge::vertical(0, 2),
+            Edge::vertical(1, 2),
+        ];
+        let mut nimstring = Nimstring::new((2, 2));
+        assert_eq!(
+            nimstring.value(&board_with(2, 2, &loop_edges)),
+            NimValue::Nimber(0)
+        );
+
+        // A single box with nothing drawn, and with one side drawn
+        let mut nimstring = Nimstring::new((1, 1));
+        assert_eq!(
+            nimstring.value(&Board::with_size(1, 1)),
+            NimValue::Nimber(1)
+        );
+        let board = board_with(1, 1, &[Edge::horizontal(0, 0)]);
+        assert_eq!(nimstring.value(&board), NimValue::Nimber(0));
+    }
+
+    #[test]
+    fn loony_positions() {
+        // Opening a long chain from its end
+        let mut board = chain(3);
+        board.mark_for(Edge::vertical(0, 0), Player::Odd);
+        let mut nimstring = Nimstring::new((1, 3));
+        assert_eq!(nimstring.value(&board), NimValue::Loony);
+
+        // Half-hearted handout: a chain of two opened from its end
+        let mut board = chain(2);
+        board.mark_for(Edge::vertical(0, 0), Player::Odd);
+        let mut nimstring = Nimstring::new((1, 2));
+        assert_eq!(nimstring.value(&board), NimValue::Loony);
+
+        // Hard-hearted handout: opened in the middle, just two coins to take
+        let mut board = chain(2);
+        board.mark_for(Edge::vertical(0, 1), Player::Odd);
+        assert_eq!(nimstring.value(&board), NimValue::Nimber(0));
+    }
+
+    #[test]
+    fn components() {
+        // Two lone coins separated by the line between them
+        let board = board_with(
+            1,
+            2,
+            &[
+                Edge::horizontal(0, 0),
+                Edge::horizontal(0, 1),
+                Edge::vertical(0, 1),
+            ],
+        );
+        let mut nimstring = Nimstring::new((1, 2));
+        let undrawn = StringsAndCoins::from_board(&board).string_mask();
+
+        assert_eq!(nimstring.components(undrawn).len(), 2);
+        assert_eq!(nimstring.value(&board), NimValue::Nimber(0));
+        assert_eq!(nimstring.components(0), vec![]);
+
+        // The whole empty board is connected
+        let board = Board::with_size(2, 3);
+        let nimstring = Nimstring::new((2, 3));
+        let undrawn = StringsAndCoins::from_board(&board).string_mask();
+        assert_eq!(nimstring.components(undrawn), vec![undrawn]);
+    }
+
+    #[test]
+    fn graphs() {
+        // A coin tied to the ground by three strings is worth nothing: any
+        // cut leaves a coin hanging by two, worth *1 to the opponent
+        let mut claw = StringsAndCoins::new(1);
+        for _ in 0..3 {
+            claw.add_string(Node::Coin(0), Node::Ground);
+        }
+        let mut nimstring = Nimstring::with_graph(&claw);
+        assert_eq!(nimstring.graph_value(&claw), NimValue::Nimber(0));
+
+        claw.cut(0);
+        assert_eq!(nimstring.graph_value(&claw), NimValue::Nimber(1));
+
+        // Two coins tied twice to each other and once each to the ground,
+        // which no grid can draw. Cutting a link leaves a chain of two worth
+        // *1, cutting a ground string a position worth nothing
+        let mut pair = StringsAndCoins::new(2);
+        pair.add_string(Node::Coin(0), Node::Coin(1));
+        pair.add_string(Node::Coin(0), Node::Coin(1));
+        pair.add_string(Node::Coin(0), Node::Ground);
+        pair.add_string(Node::Coin(1), Node::Ground);
+        let mut nimstring = Nimstring::with_graph(&pair);
+        assert_eq!(nimstring.graph_value(&pair), NimValue::Nimber(2));
+    }
+
+    #[test]
+    fn strategy_leaves_zero() {
+        let mut strategy = NimstringStrategy::new();
+        let mut game = Game::with_size(2, 2);
+        let mut nimstring = Nimstring::new((2, 2));
+
+        // From every position of a game where the player to move wins
+        // nimstring, the strategy keeps the win
+        while !game.ended() {
+            let winning = nimstring.value(game.board()).is_win();
+            let mover = game.player_to_play();
+            let edge = strategy.choose_move(&game);
+            game.play(edge);
+
+            if winning && !game.ended() {
+                let value = nimstring.value(game.board());
+                if game.player_to_play() == mover {
+                    assert!(value.is_win());
+                } else {
+                    assert!(!value.is_win());
+                }
+            }
+        }
+    }
+
+    /// Whether the player to move wins Nimstring, straight from the rules.
+    fn wins(bits: &Bitboard, undrawn: u128, memo: &mut HashMap<u128, bool>) -> bool {
+        if let Some(&win) = memo.get(&undrawn) {
+            return win;
+        }
+
+        let win = super::lines(undrawn).any(|line| {
+            let left = undrawn & !(1 << line);
+
+            if completed(bits, line, undrawn) > 0 {
+                // Another move is due, and having none loses
+                left != 0 && wins(bits, left, memo)
+            } else {
+                !wins(bits, left, memo)
+            }
+        });
+
+        memo.insert(undrawn, win);
+        win
+    }
+
+    /// How many boxes drawing `line` completes when only `undrawn` is left.
+    fn completed(bits: &Bitboard, line: usize, undrawn: u128) -> usize {
+        bits.line_boxes(line)
+            .into_iter()
+            .flatten()
+            .filter(|&index| bits.box_lines(index) & undrawn == 1 << line)
+            .count()
+    }
+
+    #[test]
+    fn matches_the_rules() {
+        for &(rows, cols) in [(1, 4), (2, 2), (2, 3)].iter() {
+            let bits = Bitboard::new(rows, cols);
+            let all = (1u128 << bits.line_count()) - 1;
+            let mut nimstring = Nimstring::new((rows, cols));
+            let mut memo = HashMap::new();
+
+            for undrawn in 0..=all {
+                assert_eq!(
+                    nimstring.value_of(undrawn).is_win(),
+                    wins(&bits, undrawn, &mut memo),
+                    "{:b}",
+                    undrawn
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,204 @@
+use std::io::{self, BufRead, Write};
+
+use crate::{
+    board::{Game, Player},
+    edge::Edge,
+    rng::Rng,
+};
+
+pub type Move = Edge;
+
+/// Anything that can pick a move for the player to play.
+pub trait Strategy {
+    /// Choose a legal move in `game`, which must not have ended.
+    fn choose_move(&mut self, game: &Game) -> Move;
+}
+
+/// Plays uniformly random legal moves.
+pub struct RandomStrategy {
+    rng: Rng,
+}
+
+impl RandomStrategy {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+        }
+    }
+}
+
+impl Default for RandomStrategy {
+    fn default() -> Self {
+        Self {
+            rng: Rng::from_time(),
+        }
+    }
+}
+
+impl Strategy for RandomStrategy {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        *self
+            .rng
+            .choose(&game.available_moves())
+            .expect("no move left to choose")
+    }
+}
+
+/// Takes a box whenever it can, otherwise plays a move that gives no box its
+/// third side, and only then gives something away.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GreedyStrategy;
+
+impl Strategy for GreedyStrategy {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        let board = game.board();
+        let moves = game.available_moves();
+
+        moves
+            .iter()
+            .find(|&&edge| board.completes_box(edge))
+            .or_else(|| moves.iter().find(|&&edge| board.is_safe(edge)))
+            .or_else(|| moves.first())
+            .copied()
+            .expect("no move left to choose")
+    }
+}
+
+/// Asks a person for moves, reading one per line from `input` and writing
+/// prompts and complaints about unreadable or illegal moves to `output`.
+pub struct HumanStrategy<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> HumanStrategy<R, W> {
+    pub fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    /// Prompt until a legal move is entered. Fails if the input ends first
+    /// or cannot be read.
+    pub fn try_choose_move(&mut self, game: &Game) -> io::Result<Move> {
+        loop {
+            write!(self.output, "{:?} to play: ", game.player_to_play())?;
+            self.output.flush()?;
+
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    "input ended before a move was chosen",
+                ));
+            }
+
+            let edge = match line.parse::<Edge>() {
+                Ok(edge) => edge,
+                Err(err) => {
+                    writeln!(self.output, "{}", err)?;
+                    continue;
+                }
+            };
+
+            match game.clone().try_play(edge) {
+                Ok(_) => return Ok(edge),
+                Err(err) => writeln!(self.output, "{}", err)?,
+            }
+        }
+    }
+}
+
+impl<R: BufRead, W: Write> Strategy for HumanStrategy<R, W> {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        match self.try_choose_move(game) {
+            Ok(edge) => edge,
+            Err(err) => panic!("{}", err),
+        }
+    }
+}
+
+/// Let `odd` and `even` play `game` to the end and return the final box
+/// difference on [`Player::Odd`] perspective.
+pub fn play_out(game: &mut Game, odd: &mut dyn Strategy, even: &mut dyn Strategy) -> i32 {
+    while !game.ended() {
+        let edge = match game.player_to_play() {
+            Player::Odd => odd.choose_move(game),
+            Player::Even => even.choose_move(game),
+        };
+
+        game.play(edge);
+    }
+
+    game.utility(Player::Odd)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Cursor, sync::Arc};
+
+    use crate::{agent::Agent, board::Game, edge::Edge};
+
+    use super::{play_out, GreedyStrategy, HumanStrategy, RandomStrategy, Strategy};
+
+    #[test]
+    fn random_games() {
+        let mut odd = RandomStrategy::new(1);
+        let mut even = RandomStrategy::new(2);
+
+        for _ in 0..20 {
+            let mut game = Game::with_size(2, 3);
+            let score = play_out(&mut game, &mut odd, &mut even);
+
+            assert!(game.ended());
+            assert_eq!(score.abs() % 2, 0);
+        }
+    }
+
+    #[test]
+    fn greedy_beats_random() {
+        let mut greedy = GreedyStrategy;
+        let mut random = RandomStrategy::new(7);
+
+        let mut wins = 0;
+        for _ in 0..20 {
+            let mut game = Game::new();
+            if play_out(&mut game, &mut greedy, &mut random) > 0 {
+                wins += 1;
+            }
+        }
+
+        assert!(wins > 10, "greedy won {} of 20 games", wins);
+    }
+
+    #[test]
+    fn agent_beats_greedy() {
+        let game = Game::with_size(2, 2);
+        let mut agent = Agent::new(Arc::new(game.clone()));
+        let mut greedy = GreedyStrategy;
+
+        // Perfect play gets at least the value of the game against anyone
+        let value = agent.ab_search().value;
+
+        let mut first = game.clone();
+        assert!(play_out(&mut first, &mut agent, &mut greedy) >= value);
+
+        let mut second = game;
+        assert!(play_out(&mut second, &mut greedy, &mut agent) <= value);
+    }
+
+    #[test]
+    fn human_input() {
+        let mut game = Game::with_size(1, 1);
+        game.play(Edge::horizontal(0, 0));
+
+        let input = Cursor::new("top\nh 0 0\nh 4 0\n0 0 left\n");
+        let mut output = vec![];
+        let mut human = HumanStrategy::new(input, &mut output);
+
+        assert_eq!(human.choose_move(&game), Edge::vertical(0, 0));
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("`top` is not a move"));
+        assert!(output.contains("`h 0 0` has already been drawn"));
+        assert!(output.contains("`h 4 0` is not on the board"));
+    }
+}
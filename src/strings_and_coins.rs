@@ -0,0 +1,446 @@
+use crate::board::Board;
+
+/// One end of a string: a coin, or the ground around the board.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Node {
+    Ground,
+    Coin(usize),
+}
+
+/// Coins of a chain in the strings-and-coins graph, in order. Each end is
+/// where the last string of the end coin leads, `None` when that coin has no
+/// string left outside the chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoinChain {
+    pub coins: Vec<usize>,
+    pub ends: [Option<Node>; 2],
+}
+
+/// The dual of a dots and boxes position: every box is a coin, every line a
+/// string tying two coins, or a coin and the ground for lines on the border.
+/// Drawing a line cuts its string and a coin with no string left is
+/// captured.
+///
+/// Strings and coins keep their numbers once cut or captured. Graphs built
+/// from a [`Board`] number coins like boxes, row by row, and strings like
+/// lines, so any graph can be built, including ones no grid could draw.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StringsAndCoins {
+    strings: Vec<[Node; 2]>,
+    cut: Vec<bool>,
+    /// Strings tied to each coin, cut or not.
+    coin_strings: Vec<Vec<usize>>,
+    captured: Vec<bool>,
+}
+
+impl StringsAndCoins {
+    /// A graph of `coins` coins and no strings.
+    pub fn new(coins: usize) -> Self {
+        Self {
+            strings: vec![],
+            cut: vec![],
+            coin_strings: vec![vec![]; coins],
+            captured: vec![false; coins],
+        }
+    }
+
+    /// The graph of `board`: its drawn lines are cut strings and its
+    /// completed boxes captured coins.
+    pub fn from_board(board: &Board) -> Self {
+        let bits = board.bits();
+        let cols = board.cols();
+        let mut graph = Self::new(bits.box_count());
+
+        for line in 0..bits.line_count() {
+            let [a, b] = bits
+                .line_boxes(line)
+                .map(|index| index.map_or(Node::Ground, |(x, y)| Node::Coin(x * cols + y)));
+
+            graph.add_string(a, b);
+            graph.cut[line] = bits.is_drawn(line);
+        }
+
+        for coin in 0..bits.box_count() {
+            graph.captured[coin] = bits.is_complete((coin / cols, coin % cols));
+        }
+
+        graph
+    }
+
+    /// Tie a new string between `a` and `b` and return its number.
+    pub fn add_string(&mut self, a: Node, b: Node) -> usize {
+        let string = self.strings.len();
+
+        for node in [a, b] {
+            if let Node::Coin(coin) = node {
+                if coin >= self.coin_count() {
+                    panic!("there is no coin {}", coin)
+                }
+
+                self.coin_strings[coin].push(string);
+                self.captured[coin] = false;
+            }
+        }
+
+        self.strings.push([a, b]);
+        self.cut.push(false);
+
+        string
+    }
+
+    /// Number of coins, captured or not.
+    pub fn coin_count(&self) -> usize {
+        self.coin_strings.len()
+    }
+
+    /// Number of strings, cut or not.
+    pub fn string_count(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub fn ends(&self, string: usize) -> [Node; 2] {
+        self.strings[string]
+    }
+
+    /// The end of `string` that is not `node`.
+    pub fn other_end(&self, string: usize, node: Node) -> Node {
+        let [a, b] = self.strings[string];
+
+        if a == node {
+            b
+        } else {
+            a
+        }
+    }
+
+    pub fn is_cut(&self, string: usize) -> bool {
+        self.cut[string]
+    }
+
+    pub fn is_captured(&self, coin: usize) -> bool {
+        self.captured[coin]
+    }
+
+    /// Strings not cut yet.
+    pub fn strings(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.strings.len()).filter(|&string| !self.cut[string])
+    }
+
+    /// Strings still tied to `coin`.
+    pub fn coin_strings(&self, coin: usize) -> impl Iterator<Item = usize> + '_ {
+        self.coin_strings[coin]
+            .iter()
+            .copied()
+            .filter(|&string| !self.cut[string])
+    }
+
+    /// Number of strings still tied to `coin`.
+    pub fn degree(&self, coin: usize) -> usize {
+        self.coin_strings(coin).count()
+    }
+
+    /// Whether `coin` hangs by a single string, whose cut captures it.
+    pub fn is_capturable(&self, coin: usize) -> bool {
+        !self.captured[coin] && self.degree(coin) == 1
+    }
+
+    /// Mask of the strings not cut yet, for graphs of at most 128 strings.
+    pub fn string_mask(&self) -> u128 {
+        if self.strings.len() > u128::BITS as usize {
+            panic!("{} strings do not fit in a mask", self.strings.len())
+        }
+
+        self.strings().fold(0, |mask, string| mask | 1 << string)
+    }
+
+    /// Cut `string` and return how many coins it captured. Cutting a string
+    /// twice captures nothing.
+    pub fn cut(&mut self, string: usize) -> usize {
+        if self.cut[string] {
+            return 0;
+        }
+
+        self.cut[string] = true;
+
+        let mut captured = 0;
+        let [a, b] = self.strings[string];
+        for node in [a, b] {
+            if let Node::Coin(coin) = node {
+                if !self.captured[coin] && self.degree(coin) == 0 {
+                    self.captured[coin] = true;
+                    captured += 1;
+                }
+            }
+        }
+
+        captured
+    }
+
+    /// Capture a coin hanging by one string by cutting it, and return how
+    /// many coins that captured: the coin at the other end may go with it.
+    pub fn capture(&mut self, coin: usize) -> usize {
+        if !self.is_capturable(coin) {
+            panic!("coin {} is not capturable", coin)
+        }
+
+        let string = self.coin_strings(coin).next().unwrap();
+        self.cut(string)
+    }
+
+    /// Split the strings left into independent groups, tied together
+    /// through coins. The ground does not tie anything.
+    pub fn components(&self) -> Vec<Vec<usize>> {
+        let mut seen = vec![false; self.strings.len()];
+        let mut components = vec![];
+
+        for string in self.strings() {
+            if seen[string] {
+                continue;
+            }
+
+            seen[string] = true;
+            let mut component = vec![];
+            let mut stack = vec![string];
+
+            while let Some(string) = stack.pop() {
+                component.push(string);
+
+                for node in self.strings[string] {
+                    if let Node::Coin(coin) = node {
+                        for next in self.coin_strings(coin) {
+                            if !seen[next] {
+                                seen[next] = true;
+                                stack.push(next);
+                            }
+                        }
+                    }
+                }
+            }
+
+            component.sort();
+            components.push(component);
+        }
+
+        components
+    }
+
+    /// Split the coins hanging by one or two strings into chains and loops.
+    /// Every such coin belongs to exactly one of them. Loops are listed as
+    /// their coins in order around the cycle.
+    pub fn chains_and_loops(&self) -> (Vec<CoinChain>, Vec<Vec<usize>>) {
+        let mut seen = vec![false; self.coin_count()];
+        let mut chains = vec![];
+        let mut loops = vec![];
+
+        for coin in 0..self.coin_count() {
+            if seen[coin] || !self.is_chained(coin) {
+                continue;
+            }
+
+            let start = self.chain_end(coin);
+            let (coins, used, closed) = self.follow_chain(start);
+
+            for &coin in coins.iter() {
+                seen[coin] = true;
+            }
+
+            if closed {
+                loops.push(coins);
+                continue;
+            }
+
+            // The strings of the end coins that do not link them to the rest
+            // of the chain lead to the ground or to a joint
+            let outer = |coin: usize| -> Vec<Node> {
+                self.coin_strings(coin)
+                    .filter(|string| !used.contains(string))
+                    .map(|string| self.other_end(string, Node::Coin(coin)))
+                    .collect()
+            };
+
+            let first = coins[0];
+            let last = coins[coins.len() - 1];
+            let ends = if coins.len() == 1 {
+                let outer = outer(first);
+                [outer.first().copied(), outer.get(1).copied()]
+            } else {
+                [outer(first).first().copied(), outer(last).first().copied()]
+            };
+
+            chains.push(CoinChain { coins, ends });
+        }
+
+        (chains, loops)
+    }
+
+    /// Coins hanging by one or two strings make up chains and loops.
+    fn is_chained(&self, coin: usize) -> bool {
+        !self.captured[coin] && (1..=2).contains(&self.degree(coin))
+    }
+
+    /// The next coin of the chain through a string of `coin` other than
+    /// `via`, with the string leading to it.
+    fn step(&self, coin: usize, via: Option<usize>) -> Option<(usize, usize)> {
+        self.coin_strings(coin)
+            .filter(|&string| Some(string) != via)
+            .find_map(|string| match self.other_end(string, Node::Coin(coin)) {
+                Node::Coin(next) if next != coin && self.is_chained(next) => Some((string, next)),
+                _ => None,
+            })
+    }
+
+    /// Walk from the chained `coin` to an end of its chain. Loops have no
+    /// end, so the walk stops before coming back to `coin`.
+    fn chain_end(&self, coin: usize) -> usize {
+        let mut via = None;
+        let mut current = coin;
+
+        while let Some((string, next)) = self.step(current, via) {
+            if next == coin {
+                break;
+            }
+
+            via = Some(string);
+            current = next;
+        }
+
+        current
+    }
+
+    /// The coins of the chain or loop from `start`, the strings linking
+    /// them and whether they close into a loop.
+    fn follow_chain(&self, start: usize) -> (Vec<usize>, Vec<usize>, bool) {
+        let mut coins = vec![start];
+        let mut used = vec![];
+        let mut via = None;
+        let mut current = start;
+
+        while let Some((string, next)) = self.step(current, via) {
+            used.push(string);
+
+            if next == start {
+                return (coins, used, true);
+            }
+
+            coins.push(next);
+            via = Some(string);
+            current = next;
+        }
+
+        (coins, used, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::{Board, Player},
+        edge::Edge,
+    };
+
+    use super::{Node, StringsAndCoins};
+
+    #[test]
+    fn from_board() {
+        let mut board = Board::with_size(1, 2);
+        board.mark_for(Edge::horizontal(0, 0), Player::Odd);
+        let graph = StringsAndCoins::from_board(&board);
+
+        assert_eq!(graph.coin_count(), 2);
+        assert_eq!(graph.string_count(), 7);
+        assert!(graph.is_cut(Edge::horizontal(0, 0).line((1, 2))));
+        assert_eq!(graph.degree(0), 3);
+        assert_eq!(graph.degree(1), 4);
+
+        let middle = Edge::vertical(0, 1).line((1, 2));
+        assert_eq!(graph.ends(middle), [Node::Coin(0), Node::Coin(1)]);
+        assert_eq!(graph.other_end(middle, Node::Coin(1)), Node::Coin(0));
+        assert_eq!(graph.components().len(), 1);
+    }
+
+    #[test]
+    fn cut_and_capture() {
+        // A coin tied to the ground twice and to a second coin hanging by
+        // that string only
+        let mut graph = StringsAndCoins::new(2);
+        let a = graph.add_string(Node::Ground, Node::Coin(0));
+        let b = graph.add_string(Node::Coin(0), Node::Ground);
+        let link = graph.add_string(Node::Coin(0), Node::Coin(1));
+
+        assert!(graph.is_capturable(1));
+        assert!(!graph.is_capturable(0));
+
+        // Taking the hanging coin leaves the other one tied to the ground
+        assert_eq!(graph.capture(1), 1);
+        assert!(graph.is_captured(1));
+        assert!(graph.is_cut(link));
+        assert_eq!(graph.degree(0), 2);
+
+        assert_eq!(graph.cut(a), 0);
+        assert_eq!(graph.cut(a), 0);
+        assert!(graph.is_capturable(0));
+        assert_eq!(graph.cut(b), 1);
+        assert!(graph.is_captured(0));
+        assert_eq!(graph.strings().count(), 0);
+        assert!(graph.components().is_empty());
+    }
+
+    #[test]
+    fn components() {
+        let mut board = Board::with_size(1, 3);
+        board.mark_for(Edge::vertical(0, 1), Player::Odd);
+        board.mark_for(Edge::vertical(0, 2), Player::Odd);
+        let graph = StringsAndCoins::from_board(&board);
+
+        // Each box is on its own between the two drawn lines
+        let components = graph.components();
+        let sizes: Vec<usize> = components.iter().map(Vec::len).collect();
+        assert_eq!(sizes, vec![3, 2, 3]);
+    }
+
+    #[test]
+    fn chains_without_grid() {
+        // A triangle of coins, each also tied to the ground: every coin has
+        // three strings, nothing is chained
+        let mut graph = StringsAndCoins::new(3);
+        for (a, b) in [(0, 1), (1, 2), (2, 0)] {
+            graph.add_string(Node::Coin(a), Node::Coin(b));
+        }
+        let grounds: Vec<usize> = (0..3)
+            .map(|coin| graph.add_string(Node::Coin(coin), Node::Ground))
+            .collect();
+        let (chains, loops) = graph.chains_and_loops();
+        assert!(chains.is_empty() && loops.is_empty());
+
+        // Without the ground strings it is a loop of three
+        let mut cycle = graph.clone();
+        for &string in grounds.iter() {
+            cycle.cut(string);
+        }
+        let (chains, loops) = cycle.chains_and_loops();
+        assert!(chains.is_empty());
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 3);
+
+        // Two coins tied together twice make a loop of two
+        let mut pair = StringsAndCoins::new(2);
+        pair.add_string(Node::Coin(0), Node::Coin(1));
+        pair.add_string(Node::Coin(1), Node::Coin(0));
+        let (chains, loops) = pair.chains_and_loops();
+        assert!(chains.is_empty());
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 2);
+
+        // Cutting one ground string leaves coin 0 joining a chain of the
+        // other two, both hanging off it
+        graph.cut(grounds[1]);
+        graph.cut(grounds[2]);
+        let (chains, loops) = graph.chains_and_loops();
+        assert!(loops.is_empty());
+        assert_eq!(chains.len(), 1);
+        let mut coins = chains[0].coins.clone();
+        coins.sort();
+        assert_eq!(coins, vec![1, 2]);
+        assert_eq!(chains[0].ends, [Some(Node::Coin(0)); 2]);
+    }
+}
@@ -0,0 +1,258 @@
+use crate::{
+    bitboard::line_count,
+    board::{Game, Player},
+    edge::Edge,
+    tile::{BoardSize, TileIndex},
+};
+
+/// A rotation or reflection of the board. Rotations are clockwise.
+///
+/// Transforms work on the dots of the board, `(rows + 1) × (cols + 1)` of
+/// them, and map edges and boxes through their corners. The quarter turns
+/// and the diagonal reflections swap rows and columns, so only the four
+/// others map a board that is not square onto itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Symmetry {
+    Identity,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Upside down.
+    FlipRows,
+    /// Left to right.
+    FlipCols,
+    /// Across the diagonal from the top left corner.
+    Transpose,
+    /// Across the diagonal from the top right corner.
+    AntiTranspose,
+}
+
+/// Every symmetry of a square board.
+pub const SYMMETRIES: [Symmetry; 8] = [
+    Symmetry::Identity,
+    Symmetry::Rotate90,
+    Symmetry::Rotate180,
+    Symmetry::Rotate270,
+    Symmetry::FlipRows,
+    Symmetry::FlipCols,
+    Symmetry::Transpose,
+    Symmetry::AntiTranspose,
+];
+
+impl Symmetry {
+    /// The symmetries mapping boards of `size` onto themselves: all eight
+    /// for square boards, four for the others.
+    pub fn of_size(size: BoardSize) -> Vec<Self> {
+        SYMMETRIES
+            .into_iter()
+            .filter(|symmetry| symmetry.image_size(size) == size)
+            .collect()
+    }
+
+    /// Whether the transform swaps rows and columns.
+    pub fn transposes(self) -> bool {
+        matches!(
+            self,
+            Self::Rotate90 | Self::Rotate270 | Self::Transpose | Self::AntiTranspose
+        )
+    }
+
+    /// The transform undoing this one.
+    pub fn inverse(self) -> Self {
+        match self {
+            Self::Rotate90 => Self::Rotate270,
+            Self::Rotate270 => Self::Rotate90,
+            other => other,
+        }
+    }
+
+    /// Size of the image of a board of `size`.
+    pub fn image_size(self, size: BoardSize) -> BoardSize {
+        if self.transposes() {
+            (size.1, size.0)
+        } else {
+            size
+        }
+    }
+
+    /// Image of the dot at `(row, col)` on a board of `size` boxes.
+    pub fn dot(self, (r, c): (usize, usize), size: BoardSize) -> (usize, usize) {
+        let (rows, cols) = size;
+
+        match self {
+            Self::Identity => (r, c),
+            Self::Rotate90 => (c, rows - r),
+            Self::Rotate180 => (rows - r, cols - c),
+            Self::Rotate270 => (cols - c, r),
+            Self::FlipRows => (rows - r, c),
+            Self::FlipCols => (r, cols - c),
+            Self::Transpose => (c, r),
+            Self::AntiTranspose => (cols - c, rows - r),
+        }
+    }
+
+    /// Image of `edge` on a board of `size`.
+    pub fn edge(self, edge: Edge, size: BoardSize) -> Edge {
+        let (r, c) = (edge.row(), edge.col());
+        let far = if edge.is_horizontal() {
+            (r, c + 1)
+        } else {
+            (r + 1, c)
+        };
+
+        let (a, b) = (self.dot((r, c), size), self.dot(far, size));
+        if a.0 == b.0 {
+            Edge::horizontal(a.0, a.1.min(b.1))
+        } else {
+            Edge::vertical(a.0.min(b.0), a.1)
+        }
+    }
+
+    /// Image of the box at `index` on a board of `size`.
+    pub fn tile(self, (x, y): TileIndex, size: BoardSize) -> TileIndex {
+        let (a, b) = (self.dot((x, y), size), self.dot((x + 1, y + 1), size));
+        (a.0.min(b.0), a.1.min(b.1))
+    }
+
+    /// Image of every line of a board of `size`: line `i` goes to the
+    /// `i`-th element.
+    pub fn lines(self, size: BoardSize) -> Vec<usize> {
+        let image = self.image_size(size);
+
+        (0..line_count(size.0, size.1))
+            .map(|line| self.edge(Edge::from_line(line, size), size).line(image))
+            .collect()
+    }
+
+    /// Image of a mask of lines of a board of `size`.
+    pub fn line_mask(self, mask: u128, size: BoardSize) -> u128 {
+        self.lines(size)
+            .into_iter()
+            .enumerate()
+            .filter(|&(line, _)| mask & (1 << line) != 0)
+            .fold(0, |image, (_, to)| image | 1 << to)
+    }
+
+    /// Image of `game`, with its history mapped move by move. Moves that
+    /// were undone are not kept.
+    pub fn game(self, game: &Game) -> Game {
+        let size = game.size();
+        let (rows, cols) = self.image_size(size);
+        let mut image = Game::with_size(rows, cols);
+
+        for outcome in game.history() {
+            image.play(self.edge(outcome.edge(), size));
+        }
+
+        image
+    }
+}
+
+/// The canonical form of `game` among its symmetric images, together with
+/// the transform leading to it. Symmetric games have the same canonical
+/// form: the image with the smallest mask of lines, then the smallest
+/// owners, box by box.
+pub fn canonical(game: &Game) -> (Game, Symmetry) {
+    Symmetry::of_size(game.size())
+        .into_iter()
+        .map(|symmetry| (symmetry.game(game), symmetry))
+        .min_by_key(|(image, _)| canonical_key(image))
+        .unwrap()
+}
+
+fn canonical_key(game: &Game) -> (u128, Vec<u8>) {
+    let bits = game.board().bits();
+    let owners = (0..bits.rows())
+        .flat_map(|x| (0..bits.cols()).map(move |y| (x, y)))
+        .map(|index| match bits.owner(index) {
+            None => 0,
+            Some(Player::Odd) => 1,
+            Some(Player::Even) => 2,
+        })
+        .collect();
+
+    (bits.lines(), owners)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        board::Game,
+        edge::Edge,
+        rng::Rng,
+        tile::{BOTTOM_RIGHT, CENTER, TOP_LEFT, TOP_RIGHT},
+    };
+
+    use super::{canonical, Symmetry, SYMMETRIES};
+
+    #[test]
+    fn transforms() {
+        let size = (3, 3);
+        assert_eq!(Symmetry::Rotate90.tile(TOP_LEFT, size), TOP_RIGHT);
+        assert_eq!(Symmetry::Rotate180.tile(TOP_LEFT, size), BOTTOM_RIGHT);
+        assert_eq!(Symmetry::AntiTranspose.tile(TOP_LEFT, size), BOTTOM_RIGHT);
+        assert!(SYMMETRIES
+            .iter()
+            .all(|symmetry| symmetry.tile(CENTER, size) == CENTER));
+
+        assert_eq!(
+            Symmetry::Rotate90.edge(Edge::horizontal(0, 0), size),
+            Edge::vertical(0, 3)
+        );
+        assert_eq!(
+            Symmetry::FlipRows.edge(Edge::horizontal(0, 1), size),
+            Edge::horizontal(3, 1)
+        );
+
+        // A 2×3 board turns into a 3×2 one
+        assert_eq!(Symmetry::Rotate90.image_size((2, 3)), (3, 2));
+        assert_eq!(Symmetry::Rotate90.tile((0, 2), (2, 3)), (2, 1));
+        assert_eq!(Symmetry::of_size((2, 3)).len(), 4);
+        assert_eq!(Symmetry::of_size((3, 3)).len(), 8);
+    }
+
+    #[test]
+    fn inverses() {
+        for &size in [(3, 3), (2, 3)].iter() {
+            for symmetry in SYMMETRIES {
+                let image = symmetry.image_size(size);
+                let lines = symmetry.lines(size);
+                let back = symmetry.inverse().lines(image);
+
+                for (line, &to) in lines.iter().enumerate() {
+                    assert_eq!(back[to], line, "{:?} on {:?}", symmetry, size);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn games() {
+        let mut rng = Rng::new(18);
+        let mut game = Game::with_size(3, 3);
+        for _ in 0..14 {
+            let edge = *rng.choose(&game.available_moves()).unwrap();
+            game.play(edge);
+        }
+
+        let mut images = vec![];
+        for symmetry in SYMMETRIES {
+            let image = symmetry.game(&game);
+            assert_eq!(image.player_to_play(), game.player_to_play());
+            assert_eq!(image.score_difference(), game.score_difference());
+            assert_eq!(
+                image.board().bits().lines(),
+                symmetry.line_mask(game.board().bits().lines(), game.size())
+            );
+            assert_eq!(symmetry.inverse().game(&image), game);
+            images.push(image);
+        }
+
+        // Every image has the same canonical form, reached by its transform
+        let (form, symmetry) = canonical(&game);
+        assert_eq!(symmetry.game(&game), form);
+        for image in images.iter() {
+            assert_eq!(canonical(image).0, form);
+        }
+    }
+}
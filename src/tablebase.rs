@@ -0,0 +1,470 @@
+use std::{
+    fs::File,
+    io::{self, BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use crate::{
+    bitboard::{line_count, Bitboard},
+    board::{Board, Game},
+    edge::Edge,
+    strategy::{Move, Strategy},
+    symmetry::Symmetry,
+    tile::BoardSize,
+};
+
+/// Boards with more lines than this are not tabulated: the table holds a
+/// nibble per line configuration, 8 MiB for the 24 lines of a 3×3 board.
+pub const MAX_LINES: usize = 24;
+
+/// First bytes of every tablebase file.
+pub const MAGIC: &[u8; 4] = b"DBTB";
+
+const VERSION: u8 = 1;
+
+/// Perfect play for every position of a small board, found by retrograde
+/// analysis.
+///
+/// What is left to win from a position depends only on the lines drawn, not
+/// on who took the boxes already completed, so the table is indexed by the
+/// mask of drawn lines and stores how many of the remaining boxes the player
+/// to move wins, one nibble per position. Positions that only differ by a
+/// symmetry of the board share their entry: only the smallest mask of each
+/// class is analysed, stored and written to disk.
+pub struct Tablebase {
+    size: BoardSize,
+    /// Lines around each box, boxes numbered row by row.
+    box_lines: Vec<u32>,
+    symmetries: Vec<LinePermutation>,
+    nibbles: Vec<u8>,
+}
+
+impl Tablebase {
+    /// Solve every position of boards of `size`. Panics if they have more
+    /// than [`MAX_LINES`] lines.
+    pub fn generate(size: BoardSize) -> Self {
+        let mut tablebase = Self::empty(size);
+        let lines = tablebase.line_count();
+
+        // Drawing a line leads to a position with one more line drawn, so
+        // going from the full board down to the empty one finds every
+        // successor solved before the positions leading to it
+        for drawn in (0..=lines).rev() {
+            for mask in masks_with(drawn, lines) {
+                if tablebase.canonical(mask) != mask {
+                    continue;
+                }
+
+                let value = tablebase.solve(mask);
+                let won = (value + tablebase.remaining(mask) as i32) / 2;
+                tablebase.set_won(mask, won as u8);
+            }
+        }
+
+        tablebase
+    }
+
+    /// Read a table written by [`Tablebase::write_to`].
+    pub fn read_from(mut reader: impl Read) -> io::Result<Self> {
+        let mut header = [0; 7];
+        reader.read_exact(&mut header)?;
+
+        if &header[..4] != MAGIC || header[4] != VERSION {
+            return Err(invalid_data("not a dots and boxes tablebase"));
+        }
+
+        let size = (header[5] as usize, header[6] as usize);
+        if size.0 == 0 || size.1 == 0 || line_count(size.0, size.1) > MAX_LINES {
+            return Err(invalid_data(
+                "the tablebase is for an unsupported board size",
+            ));
+        }
+
+        let mut tablebase = Self::empty(size);
+        let mut stored = vec![];
+        reader.read_to_end(&mut stored)?;
+
+        let mut next = 0;
+        for mask in 0..1u32 << tablebase.line_count() {
+            if tablebase.canonical(mask) != mask {
+                continue;
+            }
+
+            let byte = stored
+                .get(next / 2)
+                .ok_or_else(|| invalid_data("the tablebase is truncated"))?;
+            tablebase.set_won(mask, (byte >> (4 * (next % 2))) & 0xf);
+            next += 1;
+        }
+
+        if stored.len() != next.div_ceil(2) {
+            return Err(invalid_data("the tablebase has trailing data"));
+        }
+
+        Ok(tablebase)
+    }
+
+    /// Write the header then the entries of the canonical positions in mask
+    /// order, two per byte.
+    pub fn write_to(&self, mut writer: impl Write) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&[VERSION, self.size.0 as u8, self.size.1 as u8])?;
+
+        let mut stored = vec![];
+        let mut next = 0;
+        for mask in 0..1u32 << self.line_count() {
+            if self.canonical(mask) != mask {
+                continue;
+            }
+
+            if next % 2 == 0 {
+                stored.push(0);
+            }
+            *stored.last_mut().unwrap() |= self.won(mask) << (4 * (next % 2));
+            next += 1;
+        }
+
+        writer.write_all(&stored)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write_to(&mut writer)?;
+        writer.flush()
+    }
+
+    pub fn size(&self) -> BoardSize {
+        self.size
+    }
+
+    /// Net number of the remaining boxes the player to move wins on `board`
+    /// with perfect play from both sides.
+    pub fn value(&self, board: &Board) -> i32 {
+        self.check_size(board.size());
+        self.value_of(board.bits().lines() as u32)
+    }
+
+    /// The final box difference of `game` for the player to move, with
+    /// perfect play from here on.
+    pub fn game_value(&self, game: &Game) -> i32 {
+        game.utility(game.player_to_play()) + self.value(game.board())
+    }
+
+    /// A move keeping the value of `board` for the player to move, `None`
+    /// once every line is drawn.
+    pub fn best_move(&self, board: &Board) -> Option<Edge> {
+        self.check_size(board.size());
+
+        let mask = board.bits().lines() as u32;
+        (0..self.line_count())
+            .filter(|&line| mask & (1 << line) == 0)
+            .max_by_key(|&line| (self.move_value(mask, line), std::cmp::Reverse(line)))
+            .map(|line| Edge::from_line(line, self.size))
+    }
+
+    fn empty(size: BoardSize) -> Self {
+        let bits = Bitboard::new(size.0, size.1);
+        if bits.line_count() > MAX_LINES {
+            panic!("a {}x{} board is too large to tabulate", size.0, size.1)
+        }
+
+        let box_lines = (0..size.0)
+            .flat_map(|x| (0..size.1).map(move |y| (x, y)))
+            .map(|index| bits.box_lines(index) as u32)
+            .collect();
+
+        Self {
+            size,
+            box_lines,
+            symmetries: Symmetry::of_size(size)
+                .into_iter()
+                .map(|symmetry| LinePermutation::new(&symmetry.lines(size)))
+                .collect(),
+            nibbles: vec![0; (1 << bits.line_count()) / 2],
+        }
+    }
+
+    fn check_size(&self, size: BoardSize) {
+        if size != self.size {
+            panic!(
+                "the tablebase is for {}x{} boards, not {}x{}",
+                self.size.0, self.size.1, size.0, size.1
+            )
+        }
+    }
+
+    fn line_count(&self) -> usize {
+        line_count(self.size.0, self.size.1)
+    }
+
+    /// Best value over the moves from `mask`, nothing once every line is
+    /// drawn.
+    fn solve(&self, mask: u32) -> i32 {
+        (0..self.line_count())
+            .filter(|&line| mask & (1 << line) == 0)
+            .map(|line| self.move_value(mask, line))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Value for the player to move of drawing `line` from `mask`.
+    fn move_value(&self, mask: u32, line: usize) -> i32 {
+        let next = mask | 1 << line;
+        let completed = self
+            .box_lines
+            .iter()
+            .filter(|&&lines| lines & !mask == 1 << line)
+            .count() as i32;
+
+        if completed > 0 {
+            completed + self.value_of(next)
+        } else {
+            -self.value_of(next)
+        }
+    }
+
+    fn value_of(&self, mask: u32) -> i32 {
+        2 * self.won(self.canonical(mask)) as i32 - self.remaining(mask) as i32
+    }
+
+    /// Boxes not completed yet.
+    fn remaining(&self, mask: u32) -> u32 {
+        self.box_lines
+            .iter()
+            .filter(|&&lines| lines & mask != lines)
+            .count() as u32
+    }
+
+    /// The smallest mask among the symmetric images of `mask`.
+    fn canonical(&self, mask: u32) -> u32 {
+        self.symmetries
+            .iter()
+            .map(|symmetry| symmetry.apply(mask))
+            .min()
+            .unwrap_or(mask)
+    }
+
+    fn won(&self, mask: u32) -> u8 {
+        (self.nibbles[mask as usize / 2] >> (4 * (mask % 2))) & 0xf
+    }
+
+    fn set_won(&mut self, mask: u32, won: u8) {
+        let byte = &mut self.nibbles[mask as usize / 2];
+        let shift = 4 * (mask % 2);
+        *byte = (*byte & !(0xf << shift)) | won << shift;
+    }
+}
+
+/// Plays perfectly from the positions of its tablebase.
+pub struct TablebaseStrategy {
+    tablebase: Tablebase,
+}
+
+impl TablebaseStrategy {
+    pub fn new(tablebase: Tablebase) -> Self {
+        Self { tablebase }
+    }
+
+    pub fn tablebase(&self) -> &Tablebase {
+        &self.tablebase
+    }
+}
+
+impl Strategy for TablebaseStrategy {
+    fn choose_move(&mut self, game: &Game) -> Move {
+        self.tablebase
+            .best_move(game.board())
+            .expect("no move left to choose")
+    }
+}
+
+/// A symmetry of the board as a permutation of its lines, applied a byte of
+/// the mask at a time.
+struct LinePermutation {
+    bytes: Vec<[u32; 256]>,
+}
+
+impl LinePermutation {
+    /// The permutation sending line `i` to `lines[i]`.
+    fn new(lines: &[usize]) -> Self {
+        let bytes = lines
+            .chunks(8)
+            .map(|chunk| {
+                let mut table = [0; 256];
+                for (byte, image) in table.iter_mut().enumerate() {
+                    for (bit, &line) in chunk.iter().enumerate() {
+                        if byte & (1 << bit) != 0 {
+                            *image |= 1 << line;
+                        }
+                    }
+                }
+                table
+            })
+            .collect();
+
+        Self { bytes }
+    }
+
+    fn apply(&self, mask: u32) -> u32 {
+        self.bytes
+            .iter()
+            .enumerate()
+            .map(|(i, table)| table[(mask >> (8 * i)) as usize & 0xff])
+            .fold(0, |image, bits| image | bits)
+    }
+}
+
+/// Every mask of `len` bits with `ones` of them set, in increasing order.
+fn masks_with(ones: usize, len: usize) -> impl Iterator<Item = u32> {
+    let end = 1u64 << len;
+    let mut next = Some((1u64 << ones) - 1);
+
+    std::iter::from_fn(move || {
+        let mask = next.filter(|&mask| mask < end)?;
+
+        // Gosper's hack: the next larger number with as many bits set
+        next = (mask != 0).then(|| {
+            let low = mask & mask.wrapping_neg();
+            let ripple = mask + low;
+            ripple | (((ripple ^ mask) >> 2) / low)
+        });
+
+        Some(mask as u32)
+    })
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        agent::Agent,
+        board::Game,
+        rng::Rng,
+        strategy::{play_out, GreedyStrategy},
+    };
+
+    use super::{masks_with, Tablebase, TablebaseStrategy};
+
+    #[test]
+    fn masks() {
+        let masks: Vec<u32> = masks_with(2, 4).collect();
+        assert_eq!(masks, vec![0b0011, 0b0101, 0b0110, 0b1001, 0b1010, 0b1100]);
+        assert_eq!(masks_with(0, 3).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(masks_with(3, 3).collect::<Vec<_>>(), vec![0b111]);
+    }
+
+    #[test]
+    fn matches_alpha_beta() {
+        let mut rng = Rng::new(17);
+
+        for &(size, games, opening) in [((2, 2), 10, 0), ((2, 3), 10, 6)].iter() {
+            let tablebase = Tablebase::generate(size);
+
+            for _ in 0..games {
+                // A few random lines keep the searches short
+                let mut game = Game::with_size(size.0, size.1);
+                for _ in 0..opening {
+                    let edge = *rng.choose(&game.available_moves()).unwrap();
+                    game.play(edge);
+                }
+
+                while !game.ended() {
+                    let mut agent = Agent::new(Arc::new(game.clone()));
+
+                    assert_eq!(
+                        tablebase.game_value(&game),
+                        agent.ab_search().value,
+                        "{:?}",
+                        game.board()
+                    );
+
+                    let edge = *rng.choose(&game.available_moves()).unwrap();
+                    game.play(edge);
+                }
+            }
+        }
+    }
+
+    #[test]
+    #[ignore = "generating the 3×3 table is slow in debug builds"]
+    fn matches_alpha_beta_3x3() {
+        let mut rng = Rng::new(17);
+        let tablebase = Tablebase::generate((3, 3));
+
+        for _ in 0..12 {
+            // Mid-game positions, somewhere between a third and two thirds in
+            let mut game = Game::with_size(3, 3);
+            for _ in 0..8 + rng.below(9) {
+                let edge = *rng.choose(&game.available_moves()).unwrap();
+                game.play(edge);
+            }
+
+            let mut agent = Agent::new(Arc::new(game.clone()));
+            assert_eq!(
+                tablebase.game_value(&game),
+                agent.ab_search().value,
+                "{:?}",
+                game.board()
+            );
+        }
+    }
+
+    #[test]
+    fn symmetric_positions() {
+        let tablebase = Tablebase::generate((2, 2));
+        assert_eq!(tablebase.symmetries.len(), 8);
+        assert_eq!(Tablebase::generate((1, 2)).symmetries.len(), 4);
+
+        // Each corner of the 2×2 board is worth the same
+        let values: Vec<i32> = ["h 0 0", "h 0 1", "h 2 0", "v 1 2"]
+            .iter()
+            .map(|edge| {
+                let mut game = Game::with_size(2, 2);
+                game.play(edge.parse::<crate::edge::Edge>().unwrap());
+                tablebase.value(game.board())
+            })
+            .collect();
+        assert!(values.iter().all(|&value| value == values[0]));
+    }
+
+    #[test]
+    fn round_trip() {
+        let tablebase = Tablebase::generate((2, 3));
+        let mut bytes = vec![];
+        tablebase.write_to(&mut bytes).unwrap();
+
+        assert_eq!(&bytes[..4], b"DBTB");
+        // Symmetry leaves about a quarter of the positions to store
+        assert!(bytes.len() < (1 << 17) / 2 / 3);
+
+        let read = Tablebase::read_from(&bytes[..]).unwrap();
+        assert_eq!(read.size(), (2, 3));
+        assert!(read.nibbles == tablebase.nibbles);
+
+        assert!(Tablebase::read_from(&b"DBTX\x01\x02\x03"[..]).is_err());
+        assert!(Tablebase::read_from(&bytes[..bytes.len() - 1]).is_err());
+    }
+
+    #[test]
+    fn perfect_play() {
+        let tablebase = Tablebase::generate((2, 3));
+        let game = Game::with_size(2, 3);
+        let value = tablebase.game_value(&game);
+        let mut perfect = TablebaseStrategy::new(tablebase);
+
+        let mut first = game.clone();
+        assert!(play_out(&mut first, &mut perfect, &mut GreedyStrategy) >= value);
+
+        let mut second = game;
+        assert!(play_out(&mut second, &mut GreedyStrategy, &mut perfect) <= value);
+    }
+}
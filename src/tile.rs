@@ -1,33 +1,57 @@
-use std::{cell::RefCell, rc::Rc};
-
 pub type TileIndex = (usize, usize);
 
+/// Board dimensions in boxes, as `(rows, cols)`.
+pub type BoardSize = (usize, usize);
+
 #[derive(Debug, Clone, Copy)]
 pub struct Tile {
     index: TileIndex,
+    size: BoardSize,
     config: TileConfig,
 }
 
 impl Tile {
-    pub fn new(index: TileIndex) -> Self {
+    pub fn new(index: TileIndex, size: BoardSize) -> Self {
         Self {
             index,
+            size,
             config: TileConfig::new(),
         }
     }
 
+    /// A tile whose sides are already marked as given.
+    pub fn with_marks(
+        index: TileIndex,
+        size: BoardSize,
+        top: bool,
+        bottom: bool,
+        left: bool,
+        right: bool,
+    ) -> Self {
+        Self {
+            index,
+            size,
+            config: TileConfig::of(top, bottom, left, right),
+        }
+    }
+
     pub fn index(&self) -> TileIndex {
         self.index
     }
 
+    /// Three sides drawn: the box is the closed end of a chain and can be
+    /// taken by drawing its last side.
     pub fn is_end(&self) -> bool {
-        self.config.open_count() == 1
+        self.config.marked_count() == 3
     }
 
+    /// Two sides drawn: the box links the two boxes, or border, on its open
+    /// sides.
     pub fn is_path(&self) -> bool {
-        self.config.open_count() == 2
+        self.config.marked_count() == 2
     }
 
+    /// Whether the box belongs to a chain or a loop.
     pub fn can_be_chained(&self) -> bool {
         self.is_end() || self.is_path()
     }
@@ -46,7 +70,7 @@ impl Tile {
     }
 
     fn has_bottom_neighbor(&self) -> bool {
-        self.index.0 != 2
+        self.index.0 + 1 != self.size.0
     }
 
     fn has_left_neighbor(&self) -> bool {
@@ -54,7 +78,7 @@ impl Tile {
     }
 
     fn has_right_neighbor(&self) -> bool {
-        self.index.1 != 2
+        self.index.1 + 1 != self.size.1
     }
 
     pub fn mark(&mut self, pos: Position) {
@@ -79,7 +103,7 @@ impl Tile {
                 }
             }
             Position::Bottom => {
-                if x == 2 {
+                if x + 1 == self.size.0 {
                     return None;
                 } else {
                     x += 1;
@@ -93,7 +117,7 @@ impl Tile {
                 }
             }
             Position::Right => {
-                if y == 2 {
+                if y + 1 == self.size.1 {
                     return None;
                 } else {
                     y += 1;
@@ -108,18 +132,18 @@ impl Tile {
         self.at(pos).unwrap()
     }
 
-    pub fn adjacent_to(&self, other: &RefCell<Tile>) -> bool {
-        let x_diff = self.index.0.abs_diff(other.borrow().index.0);
-        let y_diff = self.index.1.abs_diff(other.borrow().index.1);
+    pub fn adjacent_to(&self, other: &Tile) -> bool {
+        let x_diff = self.index.0.abs_diff(other.index.0);
+        let y_diff = self.index.1.abs_diff(other.index.1);
 
         x_diff + y_diff == 1
     }
 
     // Calculate other relative position to self
-    pub fn relative_position(&self, other: &RefCell<Self>) -> Option<Position> {
+    pub fn relative_position(&self, other: &Self) -> Option<Position> {
         for &pos in POSITIONS.iter() {
             if let Some(index) = self.at(pos) {
-                if index == other.borrow().index {
+                if index == other.index {
                     return Some(pos);
                 }
             }
@@ -145,43 +169,10 @@ impl Tile {
     }
 
     pub fn is_in_edge(&self) -> bool {
-        self.index != (1, 1)
-    }
-
-    pub fn is_edge_path_chain_end(&self) -> bool {
-        if !self.is_path() || !self.is_in_edge() {
-            return false;
-        }
-
-        let openings = self.openings();
-        let opening1 = openings[0];
-        let opening2 = openings[1];
-
-        self.opening_in_edge(opening1) ^ self.opening_in_edge(opening2)
+        POSITIONS.iter().any(|&pos| !self.has_neighbor(pos))
     }
 
-    fn opening_in_edge(&self, pos: Position) -> bool {
-        match pos {
-            Position::Top => self.index.0 == 0,
-            Position::Bottom => self.index.0 == 2,
-            Position::Left => self.index.1 == 0,
-            Position::Right => self.index.1 == 2,
-        }
-    }
-
-    pub fn get_edge_path_chain_pos(&self) -> Position {
-        let openings = self.openings();
-        let opening1 = openings[0];
-        let opening2 = openings[1];
-
-        if self.has_neighbor(opening1) {
-            opening1
-        } else {
-            opening2
-        }
-    }
-
-    pub fn connected_to(&self, other: &RefCell<Tile>) -> bool {
+    pub fn connected_to(&self, other: &Tile) -> bool {
         let relpos = self.relative_position(other);
 
         if let Some(pos) = relpos {
@@ -224,7 +215,6 @@ impl TileConfig {
         }
     }
 
-    #[allow(dead_code)]
     pub fn of(top: bool, bottom: bool, left: bool, right: bool) -> Self {
         Self {
             top,
@@ -234,7 +224,7 @@ impl TileConfig {
         }
     }
 
-    pub fn open_count(&self) -> i32 {
+    pub fn marked_count(&self) -> i32 {
         self.top as i32 + self.bottom as i32 + self.left as i32 + self.right as i32
     }
 
@@ -337,26 +327,67 @@ pub const POSITIONS: [Position; 4] = [
     Position::Left,
 ];
 
-#[allow(dead_code)]
+/// How one end of a [`Chain`] carries on past its last box.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ChainEnd {
+    /// The last box has three sides drawn, nothing lies beyond it.
+    Closed,
+    /// The last box opens onto the border of the board.
+    Border,
+    /// The last box opens onto the given box, which has three or four sides
+    /// undrawn and so joins several chains.
+    Open(TileIndex),
+}
+
+/// Boxes with two or three sides drawn, each linked to the next by an
+/// undrawn line. Whoever draws a line in a chain lets the opponent take every
+/// box of it, one after the other.
+#[derive(Debug, Clone)]
 pub struct Chain {
-    first_end: (Rc<RefCell<Tile>>, Option<Position>),
-    second_end: (Rc<RefCell<Tile>>, Option<Position>),
-    tiles: Vec<Rc<RefCell<Tile>>>,
+    tiles: Vec<Tile>,
+    ends: [ChainEnd; 2],
 }
 
 impl Chain {
-    pub fn is_half_open(&self) -> bool {
-        self.openings_count() == 1
+    /// A chain of `tiles`, in order, whose first and last box end as given.
+    pub fn new(tiles: Vec<Tile>, ends: [ChainEnd; 2]) -> Self {
+        if tiles.is_empty() {
+            panic!("a chain needs at least one box")
+        }
+
+        Self { tiles, ends }
+    }
+
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
+    }
+
+    /// How the first and the last box of the chain end.
+    pub fn ends(&self) -> [ChainEnd; 2] {
+        self.ends
     }
 
+    pub fn contains(&self, index: TileIndex) -> bool {
+        self.tiles.iter().any(|tile| tile.index() == index)
+    }
+
+    /// Both ends closed: every box can be taken right away.
     pub fn is_closed(&self) -> bool {
-        self.openings_count() == 0
+        self.closed_ends() == 2
+    }
+
+    /// One end closed: the chain has been opened and can be taken from it.
+    pub fn is_half_open(&self) -> bool {
+        self.closed_ends() == 1
     }
 
+    /// No end closed: nobody can take a box of the chain yet.
     pub fn is_open(&self) -> bool {
-        self.openings_count() == 2
+        self.closed_ends() == 0
     }
 
+    /// Three boxes or more, long enough for the player taking it to decline
+    /// the last two and stay in control.
     pub fn is_long(&self) -> bool {
         self.tiles.len() > 2
     }
@@ -365,113 +396,42 @@ impl Chain {
         self.tiles.len()
     }
 
-    fn openings_count(&self) -> i32 {
-        let mut openings = 0;
-
-        if let Some(_) = self.first_end.1 {
-            openings += 1;
-        }
-
-        if let Some(_) = self.second_end.1 {
-            openings += 1;
-        }
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
 
-        openings
+    fn closed_ends(&self) -> usize {
+        self.ends
+            .iter()
+            .filter(|&&end| end == ChainEnd::Closed)
+            .count()
     }
 }
 
-pub struct ChainBuilder {
-    tiles: Vec<Rc<RefCell<Tile>>>,
-    first_end_pos: Option<Position>,
-    second_end_pos: Option<Position>,
+/// Boxes with two sides drawn linked in a cycle, at least four of them.
+#[derive(Debug, Clone)]
+pub struct Loop {
+    tiles: Vec<Tile>,
 }
 
-impl ChainBuilder {
-    pub fn new(first: Rc<RefCell<Tile>>) -> Self {
-        Self {
-            tiles: vec![first],
-            first_end_pos: None,
-            second_end_pos: None,
-        }
-    }
-
-    pub fn add(&mut self, tile: &Rc<RefCell<Tile>>) {
-        let tile_ref = tile.borrow();
-        if !tile_ref.is_end() && !tile_ref.is_path() {
-            panic!()
-        }
-
-        let last = self.tiles.last().unwrap();
-        let last_ref = last.borrow();
-
-        if !last_ref.adjacent_to(tile) {
-            panic!()
-        }
-
-        if self.tiles.len() == 1 {
-            if last_ref.is_path() {
-                let mut openings = last_ref.openings();
-                let relpos = last.borrow().relative_position(tile).unwrap();
-
-                if *openings.get(0).unwrap() == relpos {
-                    openings.remove(0);
-                } else {
-                    openings.remove(1);
-                }
-
-                self.first_end_pos = Some(*openings.get(0).unwrap());
-            }
+impl Loop {
+    /// A loop of `tiles`, in order around the cycle.
+    pub fn new(tiles: Vec<Tile>) -> Self {
+        if tiles.len() < 4 {
+            panic!("a loop needs at least four boxes")
         }
 
-        drop(last_ref);
-
-        self.tiles.push(tile.clone());
+        Self { tiles }
     }
 
-    // Build the chain, consume the builder
-    pub fn build(mut self) -> Chain {
-        let len = self.tiles.len();
-
-        if len == 1 {
-            panic!()
-        }
-
-        // let last = self.tiles.last().unwrap();
-        let last = self.tiles.last().unwrap().borrow();
-        let second_last = self.tiles.get(len - 2).unwrap();
-
-        if last.is_path() {
-            let mut openings = last.openings();
-            let relpos = last.relative_position(&second_last).unwrap();
-
-            if *openings.get(0).unwrap() == relpos {
-                openings.remove(0);
-            } else {
-                openings.remove(1);
-            }
-
-            self.second_end_pos = Some(*openings.get(0).unwrap());
-        }
-
-        drop(last);
-
-        let first = self.tiles.first().unwrap().clone();
-        let second = self.tiles.last().unwrap().clone();
-
-        Chain {
-            first_end: (first, self.first_end_pos),
-            second_end: (second, self.second_end_pos),
-            tiles: self.tiles,
-        }
+    pub fn tiles(&self) -> &[Tile] {
+        &self.tiles
     }
-}
 
-#[allow(dead_code)]
-pub struct Loop {
-    tiles: Vec<Rc<RefCell<Tile>>>,
-}
+    pub fn contains(&self, index: TileIndex) -> bool {
+        self.tiles.iter().any(|tile| tile.index() == index)
+    }
 
-impl Loop {
     pub fn is_long(&self) -> bool {
         self.tiles.len() > 4
     }
@@ -479,51 +439,9 @@ impl Loop {
     pub fn len(&self) -> usize {
         self.tiles.len()
     }
-}
-
-pub struct LoopBuilder {
-    tiles: Vec<Rc<RefCell<Tile>>>,
-}
-
-impl LoopBuilder {
-    pub fn new(first: Rc<RefCell<Tile>>) -> Self {
-        Self { tiles: vec![first] }
-    }
-
-    pub fn add(&mut self, tile: &Rc<RefCell<Tile>>) {
-        let tile_ref = tile.borrow();
-        if !tile_ref.is_path() {
-            panic!()
-        }
-
-        let last = self.tiles.last().unwrap();
-        let last_ref = last.borrow();
-
-        if !last_ref.connected_to(tile) {
-            panic!()
-        }
-
-        drop(last_ref);
-
-        self.tiles.push(tile.clone());
-    }
-
-    // Build the loop, consume the builder
-    pub fn build(self) -> Loop {
-        let len = self.tiles.len();
-
-        if len < 4 {
-            panic!()
-        }
-
-        // let last = self.tiles.last().unwrap();
-        let last = self.tiles.last().unwrap();
-        let first = self.tiles.first().unwrap();
-        if !first.borrow().connected_to(last) {
-            panic!()
-        }
 
-        Loop { tiles: self.tiles }
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
     }
 }
 
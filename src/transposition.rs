@@ -0,0 +1,268 @@
+use crate::edge::Edge;
+
+/// Number of entries in a table created with [`TranspositionTable::default`].
+pub const DEFAULT_CAPACITY: usize = 1 << 16;
+
+/// How a stored value relates to the true value of the position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bound {
+    /// The value is exact.
+    Exact,
+    /// The search failed high: the true value is at least the stored value.
+    Lower,
+    /// The search failed low: the true value is at most the stored value.
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Entry {
+    key: u64,
+    value: i32,
+    bound: Bound,
+    depth: u32,
+    best_move: Option<Edge>,
+}
+
+impl Entry {
+    pub fn new(key: u64, value: i32, bound: Bound, depth: u32, best_move: Option<Edge>) -> Self {
+        Self {
+            key,
+            value,
+            bound,
+            depth,
+            best_move,
+        }
+    }
+
+    pub fn key(&self) -> u64 {
+        self.key
+    }
+
+    pub fn value(&self) -> i32 {
+        self.value
+    }
+
+    pub fn bound(&self) -> Bound {
+        self.bound
+    }
+
+    /// How many plies below the position were searched to get the value.
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn best_move(&self) -> Option<Edge> {
+        self.best_move
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub hits: u64,
+    pub misses: u64,
+    pub stores: u64,
+}
+
+/// Fixed-size table of search results keyed by position hash. Each key maps
+/// to a single slot; a new entry replaces the old one unless the old one
+/// belongs to another position and was searched deeper.
+pub struct TranspositionTable {
+    entries: Vec<Option<Entry>>,
+    stats: TableStats,
+    /// Slots written since the table was last merged into another, if it
+    /// keeps track of them.
+    journal: Option<Vec<usize>>,
+}
+
+impl TranspositionTable {
+    pub fn new(capacity: usize) -> Self {
+        if capacity == 0 {
+            panic!("transposition table needs at least one entry")
+        }
+
+        Self {
+            entries: vec![None; capacity],
+            stats: TableStats::default(),
+            journal: None,
+        }
+    }
+
+    /// A table of `capacity` entries that keeps track of the slots written
+    /// to, so that merging it only copies those.
+    pub fn with_journal(capacity: usize) -> Self {
+        Self {
+            journal: Some(vec![]),
+            ..Self::new(capacity)
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Number of occupied slots.
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|entry| entry.is_some()).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|entry| entry.is_none())
+    }
+
+    pub fn stats(&self) -> TableStats {
+        self.stats
+    }
+
+    /// Look up the entry stored for `key`, counting a hit or a miss.
+    pub fn probe(&mut self, key: u64) -> Option<Entry> {
+        let slot = self.slot(key);
+
+        match self.entries[slot] {
+            Some(entry) if entry.key == key => {
+                self.stats.hits += 1;
+                Some(entry)
+            }
+            _ => {
+                self.stats.misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Look up the entry stored for `key` without counting it in the
+    /// statistics.
+    pub fn peek(&self, key: u64) -> Option<Entry> {
+        self.entries[self.slot(key)].filter(|entry| entry.key == key)
+    }
+
+    pub fn store(&mut self, entry: Entry) {
+        if self.replace(entry) {
+            self.stats.stores += 1;
+        }
+    }
+
+    /// Copy the entries of `other`, slot by slot, as [`Self::store`] would
+    /// but without counting them as stores: they were counted in `other`.
+    /// Only the slots written since the last merge are copied if `other`
+    /// keeps a journal, which then starts over.
+    pub fn merge(&mut self, other: &mut TranspositionTable) {
+        match other.journal.as_mut() {
+            Some(journal) => {
+                for slot in journal.drain(..) {
+                    if let Some(entry) = other.entries[slot] {
+                        self.replace(entry);
+                    }
+                }
+            }
+            None => {
+                for entry in other.entries.iter().flatten() {
+                    self.replace(*entry);
+                }
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.iter_mut().for_each(|entry| *entry = None);
+        self.stats = TableStats::default();
+        if let Some(journal) = self.journal.as_mut() {
+            journal.clear();
+        }
+    }
+
+    /// Put `entry` in its slot unless a deeper search of another position
+    /// holds it, telling whether it did.
+    fn replace(&mut self, entry: Entry) -> bool {
+        let slot = self.slot(entry.key);
+
+        if let Some(old) = self.entries[slot] {
+            if old.key != entry.key && old.depth > entry.depth {
+                return false;
+            }
+        }
+
+        self.entries[slot] = Some(entry);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.push(slot);
+        }
+        true
+    }
+
+    fn slot(&self, key: u64) -> usize {
+        (key % self.entries.len() as u64) as usize
+    }
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::edge::Edge;
+
+    use super::{Bound, Entry, TranspositionTable};
+
+    #[test]
+    fn replacement() {
+        let mut table = TranspositionTable::new(4);
+        let best = Some(Edge::vertical(0, 1));
+
+        assert!(table.probe(1).is_none());
+        table.store(Entry::new(1, 3, Bound::Exact, 5, best));
+        assert_eq!(table.probe(1).unwrap().best_move(), best);
+
+        // Same slot, shallower search of another position
+        table.store(Entry::new(5, -2, Bound::Lower, 2, None));
+        assert_eq!(table.probe(1).unwrap().value(), 3);
+        assert!(table.probe(5).is_none());
+
+        // Same position is always refreshed
+        table.store(Entry::new(1, 1, Bound::Upper, 1, None));
+        assert_eq!(table.probe(1).unwrap().bound(), Bound::Upper);
+
+        let stats = table.stats();
+        assert_eq!((stats.hits, stats.misses, stats.stores), (3, 2, 2));
+        assert_eq!(table.len(), 1);
+
+        table.clear();
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn merge() {
+        let mut table = TranspositionTable::new(4);
+        table.store(Entry::new(1, 3, Bound::Exact, 5, None));
+
+        let mut other = TranspositionTable::new(4);
+        other.store(Entry::new(5, -2, Bound::Lower, 2, None));
+        other.store(Entry::new(2, 1, Bound::Upper, 1, None));
+
+        // The deeper entry keeps its slot
+        table.merge(&mut other);
+        assert_eq!(table.peek(1).unwrap().value(), 3);
+        assert!(table.peek(5).is_none());
+        assert_eq!(table.peek(2).unwrap().bound(), Bound::Upper);
+
+        // Only the stores made in this table count
+        assert_eq!(table.stats().stores, 1);
+    }
+
+    #[test]
+    fn journal() {
+        let mut table = TranspositionTable::new(4);
+        let mut other = TranspositionTable::with_journal(4);
+        other.store(Entry::new(1, 3, Bound::Exact, 5, None));
+        table.merge(&mut other);
+        assert_eq!(table.peek(1).unwrap().value(), 3);
+
+        // Entries merged before are not copied again
+        table.clear();
+        other.store(Entry::new(2, 1, Bound::Upper, 1, None));
+        table.merge(&mut other);
+        assert!(table.peek(1).is_none());
+        assert_eq!(table.peek(2).unwrap().bound(), Bound::Upper);
+    }
+}
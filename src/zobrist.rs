@@ -0,0 +1,78 @@
+use crate::{
+    bitboard::{Bitboard, MAX_BOXES, MAX_LINES},
+    board::Player,
+    rng::splitmix64,
+};
+
+/// Random keys for every line that can be drawn on a [`Bitboard`].
+const LINE_KEYS: [u64; MAX_LINES] = keys(0x243f_6a88_85a3_08d3);
+
+/// Random keys for every score difference between the two players, offset by
+/// [`MAX_BOXES`] so the difference can be negative.
+const SCORE_KEYS: [u64; 2 * MAX_BOXES + 1] = keys(0x1319_8a2e_0370_7344);
+
+/// Key toggled whenever [`Player::Even`] is to play.
+const SIDE_KEY: u64 = splitmix64(0xa409_3822_299f_31d0).1;
+
+pub fn line_key(line: usize) -> u64 {
+    LINE_KEYS[line]
+}
+
+pub fn side_key(player: Player) -> u64 {
+    match player {
+        Player::Odd => 0,
+        Player::Even => SIDE_KEY,
+    }
+}
+
+/// Key for the boxes acquired by [`Player::Odd`] minus those acquired by
+/// [`Player::Even`].
+pub fn score_key(difference: i32) -> u64 {
+    SCORE_KEYS[(difference + MAX_BOXES as i32) as usize]
+}
+
+/// Key of the lines drawn on `bits` and of the player to play, computed from
+/// scratch. Keys of boards with different sizes are not comparable.
+pub fn position_key(bits: &Bitboard, turn: Player) -> u64 {
+    let mut key = side_key(turn);
+
+    for line in 0..bits.line_count() {
+        if bits.is_drawn(line) {
+            key ^= line_key(line);
+        }
+    }
+
+    key
+}
+
+const fn keys<const N: usize>(seed: u64) -> [u64; N] {
+    let mut keys = [0; N];
+    let mut state = seed;
+    let mut i = 0;
+
+    while i < N {
+        let (next, key) = splitmix64(state);
+        keys[i] = key;
+        state = next;
+        i += 1;
+    }
+
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::{LINE_KEYS, SCORE_KEYS, SIDE_KEY};
+
+    #[test]
+    fn distinct_keys() {
+        let mut keys = HashSet::new();
+
+        for &key in LINE_KEYS.iter().chain(SCORE_KEYS.iter()) {
+            assert!(keys.insert(key));
+        }
+        assert!(keys.insert(SIDE_KEY));
+    }
+}